@@ -1,6 +1,7 @@
 use std::{cmp};
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BitMat {
   storage: Vec<u32>,
   word_cap: usize,
@@ -12,27 +13,45 @@ impl BitMat {
     BitMat { storage: Vec::new(), word_cap: 0, bit_count: 0 }
   }
 
+  /// Creates an empty `BitMat` pre-sized to hold at least `n` elements
+  /// without reallocating.
+  pub fn with_capacity(n: usize) -> BitMat {
+    let mut bit_mat = BitMat::new();
+    bit_mat.reserve(n);
+    bit_mat
+  }
+
   pub fn count(&self) -> usize {
     self.bit_count
   }
 
+  /// Ensures the matrix can hold at least `n` elements without reallocating,
+  /// without changing `count()`. A caller who knows the number of elements
+  /// ahead of time can use this to avoid the repeated full-matrix copies that
+  /// `push` would otherwise pay as the matrix grows.
+  pub fn reserve(&mut self, n: usize) {
+    let word_cap = (n + 31) / 32;
+    if word_cap <= self.word_cap { return }
+
+    let mut new_storage = Vec::new();
+    new_storage.resize(32*word_cap*word_cap, 0);
+
+    // copy each row from the old storage to the new storage
+    for row_i in 0..self.bit_count {
+      let new_row = &mut new_storage[row_i*word_cap..(row_i+1)*word_cap];
+      let old_row = &mut self.storage[row_i*self.word_cap..(row_i+1)*self.word_cap];
+      new_row[..old_row.len()].copy_from_slice(old_row);
+    }
+
+    // replace the storage
+    self.storage = new_storage;
+    self.word_cap = word_cap;
+  }
+
   pub fn push(&mut self) {
     if self.bit_count >= self.word_cap * 32 {
-      // allocate new storage
       let new_word_cap = cmp::max(2 * self.word_cap, 1);
-      let mut new_storage = Vec::new();
-      new_storage.resize(32*new_word_cap*new_word_cap, 0);
-
-      // copy each row from the old storage to the new storage
-      for row_i in 0..self.bit_count {
-        let new_row = &mut new_storage[row_i*new_word_cap..(row_i+1)*new_word_cap];
-        let old_row = &mut self.storage[row_i*self.word_cap..(row_i+1)*self.word_cap];
-        new_row[..old_row.len()].copy_from_slice(old_row);
-      }
-
-      // replace the storage
-      self.storage = new_storage;
-      self.word_cap = new_word_cap;
+      self.reserve(new_word_cap * 32);
     }
     self.bit_count += 1;
   }
@@ -85,15 +104,80 @@ impl BitMat {
     }
   }
 
+  /// Intersects src row into dst row (dst &= src). Calls `callback` for each
+  /// bit that was set in dst but becomes cleared by the intersection.
+  pub fn bitand_row<F: FnMut(usize)>(&mut self,
+    i_dst: usize, i_src: usize, mut callback: F)
+  {
+    for word_idx in 0..self.word_cap {
+      let dst_idx = i_dst * self.word_cap + word_idx;
+      let src_idx = i_src * self.word_cap + word_idx;
+      let dst_word = self.storage[dst_idx];
+      let src_word = self.storage[src_idx];
+      self.storage[dst_idx] = dst_word & src_word;
+
+      // call the callback for each bit in destination that is newly cleared
+      let mut cleared_word = dst_word & !src_word;
+      let mut bit = 0;
+      while cleared_word != 0 {
+        let shift = cleared_word.trailing_zeros() as usize;
+        callback(32*word_idx + bit + shift);
+        cleared_word >>= shift;
+        bit += shift + 1;
+        cleared_word >>= 1;
+      }
+    }
+  }
+
+  /// Subtracts src row from dst row (dst &= !src). Calls `callback` for each
+  /// bit that was set in dst but becomes cleared by the subtraction.
+  pub fn subtract_row<F: FnMut(usize)>(&mut self,
+    i_dst: usize, i_src: usize, mut callback: F)
+  {
+    for word_idx in 0..self.word_cap {
+      let dst_idx = i_dst * self.word_cap + word_idx;
+      let src_idx = i_src * self.word_cap + word_idx;
+      let dst_word = self.storage[dst_idx];
+      let src_word = self.storage[src_idx];
+      self.storage[dst_idx] = dst_word & !src_word;
+
+      // call the callback for each bit in destination that is newly cleared
+      let mut cleared_word = dst_word & src_word;
+      let mut bit = 0;
+      while cleared_word != 0 {
+        let shift = cleared_word.trailing_zeros() as usize;
+        callback(32*word_idx + bit + shift);
+        cleared_word >>= shift;
+        bit += shift + 1;
+        cleared_word >>= 1;
+      }
+    }
+  }
+
   pub fn row_ones<'s>(&'s self, i: usize) -> impl Iterator<Item = usize> + 's {
     BitMatOnesIterator::new(self, i)
   }
 
+  /// Iterator over bits set in both row i and row j, computed word-by-word
+  /// without mutating either row.
+  pub fn row_and_ones<'s>(&'s self, i: usize, j: usize) -> impl Iterator<Item = usize> + 's {
+    BitMatAndOnesIterator::new(self, i, j)
+  }
+
   pub fn count_row_ones(&self, i: usize) -> usize {
     (0..self.word_cap).map(|word_idx|
         self.storage[i * self.word_cap + word_idx].count_ones() as usize
     ).sum()
   }
+
+  /// Counts bits set in both row i and row j, without materializing an
+  /// iterator over either row.
+  pub fn count_common(&self, i: usize, j: usize) -> usize {
+    (0..self.word_cap).map(|word_idx|
+      (self.storage[i * self.word_cap + word_idx] &
+        self.storage[j * self.word_cap + word_idx]).count_ones() as usize
+    ).sum()
+  }
 }
 
 #[derive(Debug)]
@@ -137,10 +221,66 @@ impl<'s> Iterator for BitMatOnesIterator<'s> {
   }
 }
 
+#[derive(Debug)]
+struct BitMatAndOnesIterator<'s> {
+  row_i: &'s [u32],
+  row_j: &'s [u32],
+  word_idx: usize,
+  bit_idx: usize,
+  word: u32,
+}
+
+impl<'s> BitMatAndOnesIterator<'s> {
+  fn new(bitmat: &'s BitMat, i: usize, j: usize) -> BitMatAndOnesIterator<'s> {
+    BitMatAndOnesIterator {
+      row_i: &bitmat.storage[i*bitmat.word_cap..(i+1)*bitmat.word_cap],
+      row_j: &bitmat.storage[j*bitmat.word_cap..(j+1)*bitmat.word_cap],
+      word_idx: 0,
+      bit_idx: 0,
+      word: 0,
+    }
+  }
+}
+
+impl<'s> Iterator for BitMatAndOnesIterator<'s> {
+  type Item = usize;
+
+  fn next(&mut self) -> Option<usize> {
+    while self.word == 0 {
+      if self.word_idx >= self.row_i.len() {
+        return None
+      } else {
+        self.word = self.row_i[self.word_idx] & self.row_j[self.word_idx];
+        self.word_idx += 1;
+        self.bit_idx = 0;
+      }
+    }
+
+    let shift = self.word.trailing_zeros() as usize;
+    self.bit_idx += shift + 1;
+    self.word >>= shift;
+    self.word >>= 1;
+    Some(32*(self.word_idx - 1) + (self.bit_idx - 1))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  #[test]
+  fn test_with_capacity_reserve() {
+    let bm = BitMat::with_capacity(40);
+    assert_eq!(bm.count(), 0);
+
+    let mut bm = BitMat::new();
+    bm.reserve(40);
+    for _ in 0..40 { bm.push(); }
+    assert_eq!(bm.count(), 40);
+    bm.set(10, 20);
+    assert!(bm.get(10, 20));
+  }
+
   #[test]
   fn test_push_count() {
     let mut bm = BitMat::new();
@@ -210,6 +350,52 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_bitand_row_small() {
+    let mut bm = BitMat::new();
+    for _ in 0..4 { bm.push(); }
+    bm.set(0, 0);
+    bm.set(0, 1);
+    bm.set(0, 2);
+    bm.set(1, 1);
+    bm.set(1, 3);
+
+    let mut js = Vec::new();
+    bm.bitand_row(0, 1, |j| js.push(j));
+    assert_eq!(js, vec![0, 2]);
+    assert_eq!(bm.row_ones(0).collect::<Vec<_>>(), vec![1]);
+  }
+
+  #[test]
+  fn test_subtract_row_small() {
+    let mut bm = BitMat::new();
+    for _ in 0..4 { bm.push(); }
+    bm.set(0, 0);
+    bm.set(0, 1);
+    bm.set(0, 2);
+    bm.set(1, 1);
+    bm.set(1, 3);
+
+    let mut js = Vec::new();
+    bm.subtract_row(0, 1, |j| js.push(j));
+    assert_eq!(js, vec![1]);
+    assert_eq!(bm.row_ones(0).collect::<Vec<_>>(), vec![0, 2]);
+  }
+
+  #[test]
+  fn test_count_common_and_row_and_ones() {
+    let mut bm = BitMat::new();
+    for _ in 0..4 { bm.push(); }
+    bm.set(0, 0);
+    bm.set(0, 1);
+    bm.set(0, 2);
+    bm.set(1, 1);
+    bm.set(1, 3);
+
+    assert_eq!(bm.count_common(0, 1), 1);
+    assert_eq!(bm.row_and_ones(0, 1).collect::<Vec<_>>(), vec![1]);
+  }
+
   #[test]
   fn test_large() {
     let mut bm = BitMat::new();
@@ -0,0 +1,63 @@
+use serde::{Serialize, Deserialize};
+use sha3::{Digest, Sha3_256};
+use std::{fs, path::{Path, PathBuf}};
+use crate::{Plan, Result};
+
+/// Bumped whenever the serialized `Plan` layout changes, so a cache entry
+/// left over from an older build is rejected and replanned instead of being
+/// mis-parsed by bincode.
+const CACHE_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct CacheEntryRef<'a> {
+  version: u32,
+  plan: &'a Plan,
+}
+
+#[derive(Deserialize)]
+struct CacheEntryOwned {
+  version: u32,
+  plan: Plan,
+}
+
+/// A hex-encoded SHA3-256 digest of the input bytes and the planning
+/// settings (fleet config, strategy, reroute/restitch) that affect the
+/// resulting plan, used as the cache key. `cfg_repr` should cover every
+/// `Config` field that influences planning, so that two runs of the same
+/// input with different settings never collide on the same cache entry.
+pub fn digest(input: &[u8], cfg_repr: &str) -> String {
+  let mut hasher = Sha3_256::new();
+  hasher.update(input);
+  hasher.update(b"\0");
+  hasher.update(cfg_repr.as_bytes());
+  hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn cache_path(cache_dir: &Path, digest: &str) -> PathBuf {
+  cache_dir.join(format!("{}.bin", digest))
+}
+
+/// Looks up a cached plan for the given digest. Returns `None` on a cache
+/// miss, a read/parse error, or a version mismatch; the caller should then
+/// replan and call `write_cached_plan` to fill the cache.
+pub fn read_cached_plan(cache_dir: &Path, digest: &str) -> Option<Plan> {
+  let bytes = fs::read(cache_path(cache_dir, digest)).ok()?;
+  let entry: CacheEntryOwned = bincode::deserialize(&bytes).ok()?;
+  if entry.version != CACHE_VERSION { return None }
+  Some(entry.plan)
+}
+
+/// Writes a plan to the cache directory under the given digest, creating the
+/// directory if it does not exist yet. Written via a temporary file and
+/// renamed into place, so a concurrent reader never sees a partial write.
+pub fn write_cached_plan(cache_dir: &Path, digest: &str, plan: &Plan) -> Result<()> {
+  fs::create_dir_all(cache_dir)?;
+  let entry = CacheEntryRef { version: CACHE_VERSION, plan };
+  let bytes = bincode::serialize(&entry)?;
+
+  let final_path = cache_path(cache_dir, digest);
+  let tmp_path = cache_dir.join(format!("{}.{}.bin.tmp", digest, std::process::id()));
+  fs::write(&tmp_path, bytes)?;
+  fs::rename(&tmp_path, &final_path)?;
+  Ok(())
+}
@@ -1,9 +1,12 @@
+use std::{cmp, collections::BinaryHeap};
+use fnv::{FnvHashSet};
+use serde::{Serialize, Deserialize};
 use crate::{bit_mat::BitMat};
 
 /// Maintains a transitive relation i -> j (i is-before j). The structure has
 /// slow inserts (O(N^2) worst case, O(N^3 + I) amortized over I operations) but
 /// fast queries (O(1) worst case).
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Constraints {
   /// `after[i, j]` is 1 iff i -> j
   after: BitMat,
@@ -19,14 +22,35 @@ impl Constraints {
     }
   }
 
+  /// Creates an empty `Constraints` pre-sized to hold at least `n` elements
+  /// without reallocating, for a caller who knows the number of
+  /// locations/parcels ahead of time.
+  pub fn with_capacity(n: usize) -> Constraints {
+    Constraints {
+      after: BitMat::with_capacity(n),
+      before: BitMat::with_capacity(n),
+    }
+  }
+
   /// Adds the relation i -> j to the relation. To maintain consistency, we must
   /// have `!self.is_before(j, i)`, but it is ok if `self.is_before(i, j)`.
+  /// Panics if `self.is_before(j, i)`; use `try_add_before` to recover a
+  /// witness of the conflict instead.
   pub fn add_before(&mut self, i: u32, j: u32) {
+    self.try_add_before(i, j).unwrap()
+  }
+
+  /// Like `add_before`, but instead of panicking when `self.is_before(j, i)`
+  /// already holds, returns `Err` with a chain `[j, k1, k2, ..., i]` of
+  /// existing relations witnessing why i -> j would create a cycle.
+  pub fn try_add_before(&mut self, i: u32, j: u32) -> Result<(), Vec<u32>> {
     // check that we don't have j -> i
-    assert!(!self.after.get(j as usize, i as usize));
+    if self.after.get(j as usize, i as usize) {
+      return Err(self.find_chain(j, i));
+    }
 
     // if this relation is already satisfied, we don't have to do anything
-    if self.after.set_replace(i as usize, j as usize) { return }
+    if self.after.set_replace(i as usize, j as usize) { return Ok(()) }
     self.before.set(j as usize, i as usize);
 
     // recursively fix the transitive closure:
@@ -51,6 +75,26 @@ impl Constraints {
         });
       }
     }
+
+    Ok(())
+  }
+
+  /// Finds a chain `[a, k1, k2, ..., b]` of existing relations witnessing
+  /// `self.is_before(a, b)`. Since `Constraints` stores the full transitive
+  /// closure rather than a reduction, there is no single stored edge to read
+  /// off; instead we recursively split the chain at any intermediate k with
+  /// `a -> k -> b`, falling back to the direct edge `[a, b]` once no such k
+  /// exists.
+  fn find_chain(&self, a: u32, b: u32) -> Vec<u32> {
+    match self.successors(a).find(|&k| k != b && self.is_before(k, b)) {
+      None => vec![a, b],
+      Some(k) => {
+        let mut chain = self.find_chain(a, k);
+        chain.pop();
+        chain.extend(self.find_chain(k, b));
+        chain
+      }
+    }
   }
 
   /// Adds a new element to the support set, without any constraints.
@@ -62,6 +106,11 @@ impl Constraints {
     self.before.set(i, i);
   }
 
+  /// Returns the number of elements in the support set.
+  pub fn count(&self) -> u32 {
+    self.after.count() as u32
+  }
+
   /// Checks whether i -> j is in the relation. Returns true if i == j.
   pub fn is_before(&self, i: u32, j: u32) -> bool {
     self.after.get(i as usize, j as usize)
@@ -83,6 +132,111 @@ impl Constraints {
   pub fn count_predecessors(&self, j: u32) -> u32 {
     self.before.count_row_ones(j as usize) as u32
   }
+
+  /// Iterator over all k such that i -> k and j -> k, computed in one pass
+  /// over the word arrays instead of intersecting two `successors` iterators.
+  /// Neither i nor j is included in the set.
+  pub fn common_successors<'s>(&'s self, i: u32, j: u32) -> impl Iterator<Item = u32> + 's {
+    self.after.row_and_ones(i as usize, j as usize).map(|k| k as u32)
+      .filter(move |&k| k != i && k != j)
+  }
+
+  /// Iterator over all k such that k -> i and k -> j, computed in one pass
+  /// over the word arrays instead of intersecting two `predecessors`
+  /// iterators. Neither i nor j is included in the set.
+  pub fn common_predecessors<'s>(&'s self, i: u32, j: u32) -> impl Iterator<Item = u32> + 's {
+    self.before.row_and_ones(i as usize, j as usize).map(|k| k as u32)
+      .filter(move |&k| k != i && k != j)
+  }
+
+  /// Returns a total order consistent with the partial order: if `i -> j`
+  /// then `i` precedes `j` in the result. Ties among nodes that become ready
+  /// at the same time are broken by the index `i` itself; use
+  /// `linear_extension_by` to break ties on a different key instead. On
+  /// failure (which should not happen, since the closure is always a DAG),
+  /// returns the partial result built so far.
+  pub fn linear_extension(&self) -> Result<Vec<u32>, Vec<u32>> {
+    self.linear_extension_by(|i| i)
+  }
+
+  /// Like `linear_extension`, but ties among nodes that become ready at the
+  /// same time are broken by ascending `key`, e.g. by parcel priority.
+  ///
+  /// Implemented as Kahn's algorithm directly over the stored closure: each
+  /// node starts with a remaining-predecessor count from `count_predecessors`,
+  /// a node becomes ready once its count reaches zero, and the ready set is a
+  /// `BinaryHeap` keyed on `key` so the lowest-key ready node is removed next.
+  pub fn linear_extension_by<K: Ord>(&self, mut key: impl FnMut(u32) -> K)
+    -> Result<Vec<u32>, Vec<u32>>
+  {
+    let count = self.after.count() as u32;
+    // count_predecessors(j) counts j itself (is_before(j, j) holds by
+    // convention), so subtract that reflexive count to get the number of
+    // other nodes that must be ordered before j becomes ready
+    let mut remaining: Vec<u32> = (0..count).map(|j| self.count_predecessors(j) - 1).collect();
+    let mut ready: BinaryHeap<cmp::Reverse<(K, u32)>> = (0..count)
+      .filter(|&i| remaining[i as usize] == 0)
+      .map(|i| cmp::Reverse((key(i), i)))
+      .collect();
+
+    let mut order = Vec::with_capacity(count as usize);
+    while let Some(cmp::Reverse((_, u))) = ready.pop() {
+      order.push(u);
+      for v in self.successors(u) {
+        remaining[v as usize] -= 1;
+        if remaining[v as usize] == 0 {
+          ready.push(cmp::Reverse((key(v), v)));
+        }
+      }
+    }
+
+    if order.len() == count as usize { Ok(order) } else { Err(order) }
+  }
+
+  /// Returns the transitive reduction of the relation: the minimal set of
+  /// covering edges `i -> j` such that no intermediate `k` satisfies
+  /// `i -> k -> j`. Since the relation stores the full transitive closure,
+  /// this lets callers emit a compact dependency graph instead of the dense
+  /// closure.
+  ///
+  /// Computed per source `i` by walking `successors(i)` into a covering set,
+  /// then for each `j` in that set removing every other member reachable from
+  /// `j` -- the survivors are exactly the covers of `i`.
+  pub fn transitive_reduction(&self) -> Vec<(u32, u32)> {
+    let count = self.after.count() as u32;
+    let mut edges = Vec::new();
+
+    for i in 0..count {
+      let s: Vec<u32> = self.successors(i).collect();
+      let mut covers: FnvHashSet<u32> = s.iter().cloned().collect();
+      for &j in &s {
+        for &j_prime in &s {
+          if j_prime != j && self.is_before(j, j_prime) {
+            covers.remove(&j_prime);
+          }
+        }
+      }
+
+      let mut covers: Vec<u32> = covers.into_iter().collect();
+      covers.sort_unstable();
+      edges.extend(covers.into_iter().map(|j| (i, j)));
+    }
+
+    edges
+  }
+
+  /// Merges `other` into `self`, shifting every node by `offset` -- for a
+  /// caller assembling one big `Constraints` out of several smaller ones that
+  /// each cover a disjoint, already-reserved range `offset..offset+other.count()`
+  /// of `self`'s support set. Re-adds only `other`'s transitive reduction (the
+  /// minimal covering edges) and lets `add_before`'s own transitive-closure
+  /// propagation reconstruct the rest, rather than enumerating `other`'s full
+  /// closure.
+  pub fn add_shifted_from(&mut self, other: &Constraints, offset: u32) {
+    for (pred, succ) in other.transitive_reduction() {
+      self.add_before(offset + pred, offset + succ);
+    }
+  }
 }
 
 #[cfg(test)]
@@ -98,6 +252,16 @@ mod tests {
     assert_eq!(c.predecessors(0).collect::<Vec<_>>(), vec![]);
   }
 
+  #[test]
+  fn test_with_capacity() {
+    let mut c = Constraints::with_capacity(3);
+    for _ in 0..3 { c.push(); }
+    c.add_before(0, 1);
+    c.add_before(1, 2);
+    assert!(c.is_before(0, 2));
+    assert_eq!(c.successors(0).collect::<Vec<_>>(), vec![1, 2]);
+  }
+
   #[test]
   fn test_two() {
     let mut c = Constraints::new();
@@ -191,5 +355,67 @@ mod tests {
     assert_eq!(c.predecessors(3).collect::<Vec<_>>(), vec![0, 1]);
     assert_eq!(c.predecessors(4).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
     assert_eq!(c.predecessors(5).collect::<Vec<_>>(), vec![0, 1, 3]);
+
+    assert_eq!(c.common_successors(0, 1).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    assert_eq!(c.common_successors(1, 2).collect::<Vec<_>>(), vec![4]);
+    assert_eq!(c.common_predecessors(4, 5).collect::<Vec<_>>(), vec![0, 1, 3]);
+    assert_eq!(c.common_predecessors(2, 3).collect::<Vec<_>>(), vec![0, 1]);
+
+    // the reduction should recover exactly the edges that were added, since
+    // none of them are implied by any of the others
+    assert_eq!(c.transitive_reduction(),
+      vec![(0, 1), (1, 2), (1, 3), (2, 4), (3, 4), (3, 5)]);
+  }
+
+  #[test]
+  fn test_transitive_reduction_redundant_edge() {
+    let mut c = Constraints::new();
+    for _ in 0..3 { c.push(); }
+    c.add_before(0, 1);
+    c.add_before(1, 2);
+    c.add_before(0, 2);
+
+    // 0 -> 2 is implied by 0 -> 1 -> 2, so it must not survive the reduction
+    assert_eq!(c.transitive_reduction(), vec![(0, 1), (1, 2)]);
+  }
+
+  #[test]
+  fn test_try_add_before() {
+    let mut c = Constraints::new();
+    for _ in 0..4 { c.push(); }
+    assert_eq!(c.try_add_before(0, 1), Ok(()));
+    assert_eq!(c.try_add_before(1, 2), Ok(()));
+    assert_eq!(c.try_add_before(2, 3), Ok(()));
+
+    assert_eq!(c.try_add_before(3, 0), Err(vec![0, 1, 2, 3]));
+    assert!(!c.is_before(3, 0));
+  }
+
+  #[test]
+  fn test_linear_extension() {
+    let mut c = Constraints::new();
+    for _ in 0..6 { c.push(); }
+    c.add_before(1, 2);
+    c.add_before(2, 4);
+    c.add_before(3, 5);
+    c.add_before(0, 1);
+    c.add_before(1, 3);
+    c.add_before(3, 4);
+
+    assert_eq!(c.linear_extension(), Ok(vec![0, 1, 2, 3, 4, 5]));
+  }
+
+  #[test]
+  fn test_linear_extension_by() {
+    let mut c = Constraints::new();
+    for _ in 0..5 { c.push(); }
+    for &i in &[0, 1, 2] {
+      for &j in &[3, 4] {
+        c.add_before(i, j);
+      }
+    }
+
+    assert_eq!(c.linear_extension(), Ok(vec![0, 1, 2, 3, 4]));
+    assert_eq!(c.linear_extension_by(|i| cmp::Reverse(i)), Ok(vec![2, 1, 0, 4, 3]));
   }
 }
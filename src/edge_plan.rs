@@ -1,6 +1,6 @@
 use array2d::{Array2D};
-use fnv::{FnvHashSet};
-use std::{cmp};
+use fnv::{FnvHashSet, FnvHasher};
+use std::{cmp, collections::BinaryHeap, hash::{Hash, Hasher}};
 use indicatif::{ProgressBar};
 use crate::{constraints::Constraints};
 
@@ -8,70 +8,130 @@ use crate::{constraints::Constraints};
 pub struct EdgePlan {
   pub edges: Vec<Edge>,
   pub constraints: Constraints,
-  pub min_edge_count: u32,
+  pub min_go_cost: u64,
   pub parcel_count: u32,
+  pub vertex_count: u32,
 }
 
-#[derive(Debug)]
+/// A vehicle class that can be assigned to an `Edge`: its capacity and the
+/// `go_cost` of a single journey along an edge using it. `EdgeState` keeps a
+/// fleet of classes sorted by decreasing `cap`, and `add_edge` bin-packs each
+/// edge's demand onto the cheapest class that fits it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VehicleClass {
+  pub cap: u32,
+  pub go_cost: u64,
+}
+
+#[derive(Debug, Clone)]
 pub struct Edge {
   pub src: u32,
   pub tgt: u32,
   pub free_cap: u32,
   pub cargo: Vec<EdgeCargo>,
   pub stage: Option<u32>,
+  pub vehicle_class: VehicleClass,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EdgeCargo {
   pub tgt: u32,
   pub amount: u32,
 }
 
-#[derive(Debug)]
+/// State of the edge planner. Cheaply cloneable so that a beam search
+/// (`plan_edges_all_beam`) can keep several candidate states alive at once.
+#[derive(Debug, Clone)]
 pub struct EdgeState {
   p_mat: Array2D<u32>,
   edges: Vec<Edge>,
   constraints: Constraints,
   free_out_edges: Vec<FnvHashSet<u32>>,
   vertex_count: u32,
-  edge_cap: u32,
-  min_edge_count: u32,
+  /// The fleet available for new edges, sorted by decreasing `cap`.
+  classes: Vec<VehicleClass>,
+  /// The fleet's cheapest cost-per-unit-capacity class, see `min_go_cost_for`.
+  best_class: VehicleClass,
+  min_go_cost: u64,
   parcel_count: u32,
 }
 
-/// Initializes an EdgeState structure that is used to plan edges.
-pub fn init_edge_state(vertex_count: u32, edge_cap: u32,
+impl EdgeState {
+  /// Capacity of the largest available vehicle class.
+  fn max_cap(&self) -> u32 {
+    self.classes[0].cap
+  }
+
+  /// Picks the class to use for a new edge carrying `amount` parcels: the
+  /// cheapest class whose capacity covers it, falling back to the largest
+  /// class if `amount` exceeds every class's capacity (the caller is
+  /// expected to have already split off full `max_cap` edges before this
+  /// point, so that only happens for the largest class itself).
+  fn choose_class(&self, amount: u32) -> VehicleClass {
+    self.classes.iter().cloned()
+      .filter(|class| class.cap >= amount)
+      .min_by(|a, b| a.go_cost.cmp(&b.go_cost))
+      .unwrap_or(self.classes[0])
+  }
+
+  /// Lower bound on the go_cost of shipping `count` parcels along a single
+  /// corridor: any combination of trips across the whole fleet costs at
+  /// least `count * (best_class.go_cost / best_class.cap)`, since every
+  /// class has go_cost/cap >= that ratio. (Ceiling `count` onto a single
+  /// best-ratio class, as a fleet-wide trip count, is NOT a valid lower
+  /// bound: a smaller class can plug the remainder more cheaply than a
+  /// whole extra trip of the best-ratio class.)
+  fn min_go_cost_for(&self, count: u32) -> u64 {
+    (count as u64 * self.best_class.go_cost) / self.best_class.cap as u64
+  }
+}
+
+/// Initializes an EdgeState structure that is used to plan edges. `classes`
+/// is the available fleet; it must be non-empty and is sorted internally by
+/// decreasing `cap`.
+pub fn init_edge_state(vertex_count: u32, classes: Vec<VehicleClass>,
   p_mat: Array2D<u32>) -> EdgeState
 {
-  // calculate lower bound on the number of edges
-  let min_out_edges = (0..vertex_count).map(|i| {
+  assert!(!classes.is_empty());
+  let mut classes = classes;
+  classes.sort_unstable_by(|a, b| b.cap.cmp(&a.cap));
+
+  // cheapest cost-per-unit-capacity class, see `EdgeState::min_go_cost_for`
+  let best_class = classes.iter().cloned()
+    .min_by(|a, b| (a.go_cost * b.cap as u64).cmp(&(b.go_cost * a.cap as u64)))
+    .unwrap();
+
+  let mut state = EdgeState {
+    p_mat,
+    edges: Vec::new(),
+    constraints: Constraints::with_capacity(vertex_count as usize),
+    free_out_edges: vec![FnvHashSet::default(); vertex_count as usize],
+    vertex_count, classes, best_class, min_go_cost: 0, parcel_count: 0,
+  };
+
+  // calculate lower bound on the go_cost of shipping every corridor's demand
+  let min_out_go_cost = (0..vertex_count).map(|i| {
       let out_count = (0..vertex_count).filter(|&j| j != i).map(|j|
-        p_mat[(i as usize, j as usize)]
+        state.p_mat[(i as usize, j as usize)]
       ).sum::<u32>();
-      (out_count + edge_cap - 1) / edge_cap
-    }).sum();
-  let min_in_edges = (0..vertex_count).map(|j| {
+      state.min_go_cost_for(out_count)
+    }).sum::<u64>();
+  let min_in_go_cost = (0..vertex_count).map(|j| {
       let in_count = (0..vertex_count).filter(|&i| i != j).map(|i|
-        p_mat[(i as usize, j as usize)]
+        state.p_mat[(i as usize, j as usize)]
       ).sum::<u32>();
-      (in_count + edge_cap - 1) / edge_cap
-    }).sum();
-  let min_edge_count = cmp::max(min_out_edges, min_in_edges);
+      state.min_go_cost_for(in_count)
+    }).sum::<u64>();
+  state.min_go_cost = cmp::max(min_out_go_cost, min_in_go_cost);
 
   // calculate number of parcels
-  let parcel_count = (0..vertex_count).map(|i|
+  state.parcel_count = (0..vertex_count).map(|i|
       (0..vertex_count).filter(|&j| j != i).map(|j|
-        p_mat[(i as usize, j as usize)]
+        state.p_mat[(i as usize, j as usize)]
       ).sum::<u32>()
     ).sum();
 
-  EdgeState {
-    p_mat,
-    edges: Vec::new(),
-    constraints: Constraints::new(),
-    free_out_edges: vec![FnvHashSet::default(); vertex_count as usize],
-    vertex_count, edge_cap, min_edge_count, parcel_count,
-  }
+  state
 }
 
 /// Converts an EdgeState which contains planned edges and constraints to a
@@ -80,8 +140,9 @@ pub fn plan_edges(state: EdgeState) -> EdgePlan {
   EdgePlan {
     edges: state.edges,
     constraints: state.constraints,
-    min_edge_count: state.min_edge_count,
+    min_go_cost: state.min_go_cost,
     parcel_count: state.parcel_count,
+    vertex_count: state.vertex_count,
   }
 }
 
@@ -126,8 +187,8 @@ fn plan_hub_dir(state: &mut EdgeState, hub: u32,
   vertices.sort_unstable_by(|&v1, &v2| {
     let (src1, tgt1) = src_tgt(v1, hub, hubward);
     let (src2, tgt2) = src_tgt(v2, hub, hubward);
-    let p1 = state.p_mat[(src1 as usize, tgt1 as usize)] % state.edge_cap;
-    let p2 = state.p_mat[(src2 as usize, tgt2 as usize)] % state.edge_cap;
+    let p1 = state.p_mat[(src1 as usize, tgt1 as usize)] % state.max_cap();
+    let p2 = state.p_mat[(src2 as usize, tgt2 as usize)] % state.max_cap();
     p2.cmp(&p1)
   });
   bar.set_length(vertices.len() as u64);
@@ -143,10 +204,11 @@ fn plan_hub_dir(state: &mut EdgeState, hub: u32,
     let (src, tgt) = src_tgt(vertex, hub, hubward);
     let mut amount = state.p_mat[(src as usize, tgt as usize)];
 
-    // add fully saturated edges src -> tgt
-    while amount >= state.edge_cap {
-      add_edge(state, src, tgt, state.edge_cap, Some(stage));
-      amount -= state.edge_cap;
+    // add fully saturated edges src -> tgt, using the largest class first
+    while amount >= state.max_cap() {
+      let cap = state.max_cap();
+      add_edge(state, src, tgt, cap, cap, Some(stage));
+      amount -= cap;
     }
 
     if amount > 0 {
@@ -156,9 +218,10 @@ fn plan_hub_dir(state: &mut EdgeState, hub: u32,
         // extend an existing path
         let mut hub_path = hub_paths.swap_remove(path_i);
 
-        // add an edge that extends the the hub path
+        // add an edge that extends the the hub path; it carries no cargo
+        // yet, but must be able to hold amount once send_along_edge runs
         let (add_src, add_tgt) = src_tgt(vertex, hub_path.vertex, hubward);
-        let added_idx = add_edge(state, add_src, add_tgt, 0, Some(stage));
+        let added_idx = add_edge(state, add_src, add_tgt, 0, amount, Some(stage));
 
         // send parcels along the edges in the path
         for &edge_idx in hub_path.edges.iter() {
@@ -171,17 +234,21 @@ fn plan_hub_dir(state: &mut EdgeState, hub: u32,
           added_idx, *hub_path.edges.last().unwrap(), hubward);
         state.constraints.add_before(before_idx, after_idx);
 
-        if hub_path.free_cap > amount {
+        // the path's free_cap is now bottlenecked by whichever is smaller:
+        // the rest of the chain, or the class just chosen for the new edge
+        let new_free_cap = cmp::min(
+          hub_path.free_cap - amount, state.edges[added_idx as usize].free_cap);
+        if new_free_cap > 0 {
           // update the path and add it back to hub_paths
           hub_path.vertex = vertex;
           hub_path.edges.push(added_idx);
-          hub_path.free_cap -= amount;
+          hub_path.free_cap = new_free_cap;
           hub_paths.push(hub_path);
         }
       } else {
         // start a new hub path
-        let edge_idx = add_edge(state, src, tgt, amount, Some(stage));
-        let free_cap = state.edge_cap - amount;
+        let edge_idx = add_edge(state, src, tgt, amount, amount, Some(stage));
+        let free_cap = state.edges[edge_idx as usize].free_cap;
         hub_paths.push(HubPath { vertex, edges: vec![edge_idx], free_cap });
       }
     }
@@ -191,40 +258,79 @@ fn plan_hub_dir(state: &mut EdgeState, hub: u32,
   }
 }
 
-/// Plan all remaining edges between vertices, using stage None.
-pub fn plan_edges_all(state: &mut EdgeState, bar: &ProgressBar) {
-  bar.reset();
-  bar.set_message("planning edges");
-
-  // compute the heuristic order of (src, tgt) pairs: decreasing by p_mat(src,
-  // tgt)
+/// Computes the heuristic order of (src, tgt) pairs with outstanding demand:
+/// decreasing by p_mat(src, tgt) modulo the largest class's cap.
+fn order_src_tgts(state: &EdgeState) -> Vec<(u32, u32)> {
   let mut src_tgts: Vec<_> = (0..state.vertex_count)
     .map(|src| (0..state.vertex_count).map(move |tgt| (src, tgt))).flatten()
     .filter(|&(src, tgt)| src != tgt && state.p_mat[(src as usize, tgt as usize)] > 0)
     .collect();
   src_tgts.sort_unstable_by(|&(src1, tgt1), &(src2, tgt2)| {
-    let p1 = state.p_mat[(src1 as usize, tgt1 as usize)] % state.edge_cap;
-    let p2 = state.p_mat[(src2 as usize, tgt2 as usize)] % state.edge_cap;
+    let p1 = state.p_mat[(src1 as usize, tgt1 as usize)] % state.max_cap();
+    let p2 = state.p_mat[(src2 as usize, tgt2 as usize)] % state.max_cap();
     p2.cmp(&p1)
   });
+  src_tgts
+}
 
+/// Selects which algorithm `plan_edges_all_with_strategy` uses to build the
+/// unconstrained edges. This lets a caller (or the command line) pick a
+/// planner at runtime instead of the one hard-wired choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+  /// Plain Dijkstra over free edges, without the lookahead heuristic.
+  Greedy,
+  /// Dijkstra with the admissible lookahead heuristic. The heuristic changes
+  /// the order in which vertices are popped, so among equally short paths
+  /// (and, through `can_use_edge`, in general) the path found can differ
+  /// from the one `Greedy` finds.
+  AStar,
+  /// Beam search keeping `beam_width` candidate states (see
+  /// `plan_edges_all_beam`).
+  Beam(u32),
+}
+
+/// Plans all remaining edges between vertices (stage None) using the given
+/// strategy.
+pub fn plan_edges_all_with_strategy(state: &mut EdgeState, strategy: Strategy, bar: &ProgressBar) {
+  match strategy {
+    Strategy::Greedy => plan_edges_all_impl(state, false, bar),
+    Strategy::AStar => plan_edges_all_impl(state, true, bar),
+    Strategy::Beam(beam_width) => plan_edges_all_beam(state, beam_width, bar),
+  }
+}
+
+/// Plan all remaining edges between vertices, using stage None. Equivalent to
+/// `plan_edges_all_with_strategy(state, Strategy::AStar, bar)`.
+pub fn plan_edges_all(state: &mut EdgeState, bar: &ProgressBar) {
+  plan_edges_all_impl(state, true, bar)
+}
+
+fn plan_edges_all_impl(state: &mut EdgeState, use_heuristic: bool, bar: &ProgressBar) {
+  bar.reset();
+  bar.set_message("planning edges");
+
+  let src_tgts = order_src_tgts(state);
   bar.set_length(src_tgts.len() as u64);
   for (src, tgt) in src_tgts {
     let mut amount = state.p_mat[(src as usize, tgt as usize)];
 
-    while amount >= state.edge_cap {
-      // there are enough parcels to add fully saturated edges src -> tgt
-      add_edge(state, src, tgt, state.edge_cap, None);
-      amount -= state.edge_cap;
+    while amount >= state.max_cap() {
+      // there are enough parcels to add fully saturated edges src -> tgt,
+      // using the largest class first
+      let cap = state.max_cap();
+      add_edge(state, src, tgt, cap, cap, None);
+      amount -= cap;
     }
 
     if amount > 0 {
-      if let Some(path) = find_path(&state, src, tgt, amount) {
+      if let Some(path) = find_path(&state, src, tgt, amount, use_heuristic) {
         // there is a path src -> tgt, send parcels along it
         augment_path(state, tgt, &path, amount);
       } else {
-        // add an unsaturated edge src -> tgt
-        add_edge(state, src, tgt, amount, None);
+        // add an unsaturated edge src -> tgt, using the cheapest class that
+        // covers the remainder
+        add_edge(state, src, tgt, amount, amount, None);
       }
     }
 
@@ -233,6 +339,121 @@ pub fn plan_edges_all(state: &mut EdgeState, bar: &ProgressBar) {
   }
 }
 
+/// Like `plan_edges_all`, but keeps a beam of up to `beam_width` candidate
+/// states instead of committing to the single greedy choice at each (src,
+/// tgt) step. At every step each live state branches into: augmenting along
+/// the best path found by `find_path`, augmenting along the best disjoint
+/// path that avoids those edges, and opening a fresh edge. Candidates are
+/// deduplicated by a fingerprint of their edge multiset and the `beam_width`
+/// lowest-scoring survivors (by the go_cost of the edges committed so far
+/// plus the remaining go_cost lower bound from `remaining_lower_bound`) are
+/// kept. With `beam_width == 1` this reproduces `plan_edges_all` exactly.
+pub fn plan_edges_all_beam(state: &mut EdgeState, beam_width: u32, bar: &ProgressBar) {
+  assert!(beam_width >= 1);
+  if beam_width == 1 {
+    plan_edges_all(state, bar);
+    return;
+  }
+
+  bar.reset();
+  bar.set_message("planning edges (beam search)");
+
+  let src_tgts = order_src_tgts(state);
+  bar.set_length(src_tgts.len() as u64);
+
+  let mut beam = vec![state.clone()];
+  for (step, &(src, tgt)) in src_tgts.iter().enumerate() {
+    let remaining = &src_tgts[step+1..];
+    let mut seen = FnvHashSet::default();
+    let mut candidates = Vec::new();
+
+    for live_state in beam.drain(..) {
+      for candidate in branch_edge_candidates(live_state, src, tgt) {
+        if seen.insert(edge_state_fingerprint(&candidate)) {
+          candidates.push(candidate);
+        }
+      }
+    }
+
+    candidates.sort_by_cached_key(|candidate|
+      committed_go_cost(candidate) + remaining_lower_bound(candidate, remaining));
+    candidates.truncate(beam_width as usize);
+    beam = candidates;
+    bar.inc(1);
+  }
+
+  *state = beam.into_iter()
+    .min_by_key(committed_go_cost)
+    .expect("the beam must retain at least one candidate state");
+}
+
+/// Total go_cost of the edges a candidate state has committed to so far.
+fn committed_go_cost(state: &EdgeState) -> u64 {
+  state.edges.iter().map(|edge| edge.vehicle_class.go_cost).sum()
+}
+
+/// Branches a single beam state over the candidate ways to satisfy the
+/// demand from src to tgt (after peeling off any fully saturated edges,
+/// which every candidate needs regardless of the augmenting strategy chosen).
+fn branch_edge_candidates(mut live: EdgeState, src: u32, tgt: u32) -> Vec<EdgeState> {
+  let mut amount = live.p_mat[(src as usize, tgt as usize)];
+  while amount >= live.max_cap() {
+    let cap = live.max_cap();
+    add_edge(&mut live, src, tgt, cap, cap, None);
+    amount -= cap;
+  }
+  live.p_mat[(src as usize, tgt as usize)] = 0;
+
+  if amount == 0 {
+    return vec![live];
+  }
+
+  let mut candidates = Vec::new();
+  if let Some(path) = find_path(&live, src, tgt, amount, true) {
+    // candidate: augment along the best path
+    let mut branched = live.clone();
+    augment_path(&mut branched, tgt, &path, amount);
+    candidates.push(branched);
+
+    // candidate: augment along the best path disjoint from the one above
+    let exclude: FnvHashSet<u32> = path.iter().cloned().collect();
+    if let Some(alt_path) = find_path_excluding(&live, src, tgt, amount, &exclude, true) {
+      let mut branched_alt = live.clone();
+      augment_path(&mut branched_alt, tgt, &alt_path, amount);
+      candidates.push(branched_alt);
+    }
+  }
+
+  // candidate: open a fresh edge instead of reusing an existing path
+  let mut opened = live;
+  add_edge(&mut opened, src, tgt, amount, amount, None);
+  candidates.push(opened);
+
+  candidates
+}
+
+/// Lower bound on the go_cost still needed for the unprocessed (src, tgt)
+/// demand, used to score partial beam-search states.
+fn remaining_lower_bound(state: &EdgeState, remaining: &[(u32, u32)]) -> u64 {
+  remaining.iter().map(|&(src, tgt)| {
+    let amount = state.p_mat[(src as usize, tgt as usize)];
+    state.min_go_cost_for(amount)
+  }).sum()
+}
+
+/// Fingerprint of a state's edge multiset, used to deduplicate beam
+/// candidates that ended up in the same place via different branches.
+fn edge_state_fingerprint(state: &EdgeState) -> u64 {
+  let mut rows: Vec<_> = state.edges.iter()
+    .map(|edge| (edge.src, edge.tgt, edge.cargo.iter().map(|c| c.amount).sum::<u32>()))
+    .collect();
+  rows.sort_unstable();
+
+  let mut hasher = FnvHasher::default();
+  rows.hash(&mut hasher);
+  hasher.finish()
+}
+
 /// Applies an augmenting path by sending parcels along its edges.
 fn augment_path(state: &mut EdgeState, tgt: u32, path: &[u32], amount: u32) {
   for (path_idx, &edge_idx) in path.iter().enumerate() {
@@ -243,22 +464,27 @@ fn augment_path(state: &mut EdgeState, tgt: u32, path: &[u32], amount: u32) {
   }
 }
 
-/// Adds a new edge src -> tgt and sends given amount of parcels along it.
+/// Adds a new edge src -> tgt and sends given amount of parcels along it. The
+/// edge is assigned the cheapest vehicle class whose cap covers `min_cap`
+/// (which must be >= amount, but may exceed it when the edge is created ahead
+/// of parcels that `send_along_edge` will add to it later).
 fn add_edge(state: &mut EdgeState, src: u32, tgt: u32,
-  amount: u32, stage: Option<u32>) -> u32
+  amount: u32, min_cap: u32, stage: Option<u32>) -> u32
 {
-  assert!(amount <= state.edge_cap);
+  assert!(amount <= min_cap);
+  let vehicle_class = state.choose_class(min_cap);
   let edge_idx = state.edges.len() as u32;
 
   state.edges.push(Edge {
     src, tgt,
-    free_cap: state.edge_cap - amount,
+    free_cap: vehicle_class.cap - amount,
     cargo: vec![EdgeCargo { tgt, amount }],
     stage,
+    vehicle_class,
   });
   state.constraints.push();
 
-  if amount < state.edge_cap {
+  if amount < vehicle_class.cap {
     state.free_out_edges[src as usize].insert(edge_idx);
   }
 
@@ -288,10 +514,53 @@ fn send_along_edge(state: &mut EdgeState, edge_idx: u32, tgt: u32, amount: u32)
   }
 }
 
+/// An entry in the best-first search frontier of `find_path`. Ordered so that
+/// a `BinaryHeap` (a max-heap) pops the entry with the lowest `key` first,
+/// breaking ties by fewer predecessors, then by larger `free_cap`.
+#[derive(PartialEq, Eq)]
+struct PathHeapEntry {
+  key: u32,
+  cost: u32,
+  pred_count: u32,
+  free_cap: u32,
+  vertex: u32,
+}
+
+impl Ord for PathHeapEntry {
+  fn cmp(&self, other: &PathHeapEntry) -> cmp::Ordering {
+    other.key.cmp(&self.key)
+      .then_with(|| other.pred_count.cmp(&self.pred_count))
+      .then_with(|| self.free_cap.cmp(&other.free_cap))
+  }
+}
+
+impl PartialOrd for PathHeapEntry {
+  fn partial_cmp(&self, other: &PathHeapEntry) -> Option<cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
 /// Attempts to find a free path from path_src to path_tgt with capacity at
-/// least min_cap.
-fn find_path(state: &EdgeState, path_src: u32, path_tgt: u32, min_cap: u32)
-  -> Option<Vec<u32>>
+/// least min_cap. Uses a Dijkstra/A*-style best-first search over edges so
+/// that the returned path has the minimal number of hops (each hop becomes an
+/// extra Unload later on), which a plain level-by-level BFS only guarantees
+/// per-layer rather than globally. When `use_heuristic` is false this is a
+/// plain Dijkstra search (Strategy::Greedy); when true it additionally uses
+/// the admissible lookahead heuristic to steer the search towards path_tgt
+/// (Strategy::AStar). Each vertex keeps the first predecessor edge that
+/// reached it at its best cost, and `can_use_edge` checks the path recorded
+/// so far, so a different pop order can yield a different path.
+fn find_path(state: &EdgeState, path_src: u32, path_tgt: u32, min_cap: u32,
+  use_heuristic: bool) -> Option<Vec<u32>>
+{
+  let no_exclude = FnvHashSet::default();
+  find_path_excluding(state, path_src, path_tgt, min_cap, &no_exclude, use_heuristic)
+}
+
+/// Like `find_path`, but ignores edges whose index is in `exclude`. Used by
+/// the beam search to look for a second, disjoint augmenting path.
+fn find_path_excluding(state: &EdgeState, path_src: u32, path_tgt: u32, min_cap: u32,
+  exclude: &FnvHashSet<u32>, use_heuristic: bool) -> Option<Vec<u32>>
 {
   assert!(path_src != path_tgt);
 
@@ -307,38 +576,53 @@ fn find_path(state: &EdgeState, path_src: u32, path_tgt: u32, min_cap: u32)
     }
   }
 
-  // runs a breadth-first search over edges, stopping when a path to path_tgt is
-  // found
-  let mut current_vertices = vec![path_src];
+  // admissible heuristic: the current vertex still needs at least one more
+  // hop unless it already has a free edge straight to path_tgt
+  let heuristic = |vertex: u32| -> u32 {
+    if !use_heuristic { return 0 }
+    let has_direct_edge = state.free_out_edges[vertex as usize].iter().any(|&edge_idx| {
+      let edge = &state.edges[edge_idx as usize];
+      edge.tgt == path_tgt && edge.free_cap >= min_cap && !exclude.contains(&edge_idx)
+    });
+    if has_direct_edge { 0 } else { 1 }
+  };
+
+  let mut dist = vec![u32::max_value(); state.vertex_count as usize];
   let mut edges_to = vec![!0; state.vertex_count as usize];
+  let mut heap = BinaryHeap::new();
 
-  'bfs: while !current_vertices.is_empty() {
-    let mut next_edges = current_vertices.into_iter()
-      .flat_map(|vertex| state.free_out_edges[vertex as usize].iter().cloned())
-      .filter(|&next_edge_idx| {
-        let next_edge = &state.edges[next_edge_idx as usize];
-        next_edge.tgt != path_src
-          && edges_to[next_edge.tgt as usize] == !0
-          && next_edge.free_cap >= min_cap
-          && can_use_edge(state, &edges_to, next_edge.src, next_edge_idx)
-      })
-      .collect::<Vec<_>>();
-    next_edges.sort_by_cached_key(|&next_edge_idx| {
-      let pred_count = state.constraints.count_predecessors(next_edge_idx);
-      let free_cap = state.edges[next_edge_idx as usize].free_cap;
-      (pred_count, cmp::Reverse(free_cap))
-    });
+  dist[path_src as usize] = 0;
+  heap.push(PathHeapEntry {
+    key: heuristic(path_src), cost: 0,
+    pred_count: 0, free_cap: u32::max_value(),
+    vertex: path_src,
+  });
 
-    let mut next_vertices = vec![];
-    for next_edge_idx in next_edges {
-      let next_vertex = state.edges[next_edge_idx as usize].tgt;
-      if edges_to[next_vertex as usize] == !0 {
-        edges_to[next_vertex as usize] = next_edge_idx;
-        if next_vertex == path_tgt { break 'bfs }
-        next_vertices.push(next_vertex);
+  while let Some(entry) = heap.pop() {
+    if entry.cost > dist[entry.vertex as usize] { continue } // stale entry
+    if entry.vertex == path_tgt { break }
+
+    for &next_edge_idx in state.free_out_edges[entry.vertex as usize].iter() {
+      let next_edge = &state.edges[next_edge_idx as usize];
+      if next_edge.tgt == path_src
+        || next_edge.free_cap < min_cap
+        || exclude.contains(&next_edge_idx)
+        || !can_use_edge(state, &edges_to, entry.vertex, next_edge_idx)
+      { continue }
+
+      let next_cost = entry.cost + 1;
+      if next_cost < dist[next_edge.tgt as usize] {
+        dist[next_edge.tgt as usize] = next_cost;
+        edges_to[next_edge.tgt as usize] = next_edge_idx;
+        heap.push(PathHeapEntry {
+          key: next_cost + heuristic(next_edge.tgt),
+          cost: next_cost,
+          pred_count: state.constraints.count_predecessors(next_edge_idx),
+          free_cap: next_edge.free_cap,
+          vertex: next_edge.tgt,
+        });
       }
     }
-    current_vertices = next_vertices;
   }
 
   // reconstruct the path to path_tgt from edges_to[]
@@ -356,3 +640,173 @@ fn find_path(state: &EdgeState, path_src: u32, path_tgt: u32, min_cap: u32)
     None
   }
 }
+
+/// Re-routes cargo on the already-planned edges via a per-destination
+/// min-cost flow, so that the total number of edge traversals (each one a
+/// `transfer_cost`-charged Unload) is minimized globally instead of being an
+/// artifact of the order the greedy/beam construction processed `(src, tgt)`
+/// pairs in. Treats each destination vertex as a single commodity and, for
+/// each one, unwinds its current assignment back into free capacity and
+/// re-derives it from scratch via a successive-shortest-path min-cost flow
+/// over the residual graph (forward arcs with the remaining capacity at
+/// cost +1, backward arcs that cancel flow already pushed *by this pass* at
+/// cost -1, so one source saturating a bottleneck can never strand a later
+/// source), then rewrites the `EdgeCargo` amounts and `Constraints` ordering
+/// to match. Never adds edges, so `edges.len()` never increases, and never
+/// raises the total arc count used by any commodity, so the resulting
+/// plan's cost is <= the input's. Stage-agnostic: it does not distinguish
+/// hub-bound from rim-bound edges, so callers that rely on
+/// `plan_edges_hub`'s stage ordering should treat a rerouted plan's
+/// hub/rim split as advisory only.
+pub fn reroute_min_cost_flow(plan: &mut EdgePlan) {
+  for tgt in 0..plan.vertex_count {
+    reroute_commodity(plan, tgt);
+  }
+}
+
+/// Re-routes the flow of parcels destined for `tgt` over the edges in `plan`.
+fn reroute_commodity(plan: &mut EdgePlan, tgt: u32) {
+  let vertex_count = plan.vertex_count as usize;
+  let edge_count = plan.edges.len();
+
+  let carried_amount = |edge: &Edge| -> u32 {
+    edge.cargo.iter().find(|c| c.tgt == tgt).map(|c| c.amount).unwrap_or(0)
+  };
+
+  // supply[v] = net divergence of commodity tgt at v: positive at the
+  // vertices where this cargo truly originates, very negative at tgt itself
+  // (which only ever consumes it), zero at pure transit vertices. Computed
+  // from the existing assignment before it is unwound below.
+  let mut supply = vec![0i64; vertex_count];
+  for edge in plan.edges.iter() {
+    let amount = carried_amount(edge) as i64;
+    supply[edge.src as usize] += amount;
+    supply[edge.tgt as usize] -= amount;
+  }
+  if supply.iter().all(|&s| s == 0) { return }
+
+  // cap[e] = total capacity edge e can carry of commodity tgt: restore the
+  // amount currently assigned to tgt back into free capacity, so the
+  // assignment is re-derived from a clean slate below instead of being
+  // layered on top of the very flow it is meant to replace
+  let cap: Vec<u32> = plan.edges.iter().map(|edge| edge.free_cap + carried_amount(edge)).collect();
+  // carried[e] = amount of commodity tgt assigned to edge e by this pass
+  let mut carried: Vec<u32> = vec![0; edge_count];
+
+  // adjacency of (edge_idx, is_forward) residual arcs per vertex. The
+  // backward arc of e lets a later source's path cancel part of an earlier
+  // source's push through e, so one source saturating a shared bottleneck
+  // edge cannot strand a later source that needed it -- without it, the
+  // per-source greedy search can get permanently stuck even when a feasible
+  // full rerouting exists.
+  let mut out_arcs: Vec<Vec<(usize, bool)>> = vec![Vec::new(); vertex_count];
+  for (edge_idx, edge) in plan.edges.iter().enumerate() {
+    out_arcs[edge.src as usize].push((edge_idx, true));
+    out_arcs[edge.tgt as usize].push((edge_idx, false));
+  }
+
+  let arc_cap = |edge_idx: usize, forward: bool, carried: &[u32]| -> u32 {
+    if forward { cap[edge_idx] - carried[edge_idx] } else { carried[edge_idx] }
+  };
+  let arc_cost = |forward: bool| -> i64 { if forward { 1 } else { -1 } };
+  let arc_head = |edge_idx: usize, forward: bool, edges: &[Edge]| -> u32 {
+    if forward { edges[edge_idx].tgt } else { edges[edge_idx].src }
+  };
+
+  // Johnson potentials, used to keep Dijkstra valid despite the negative-cost
+  // backward arcs. No Bellman-Ford pass is needed to seed them: the
+  // assignment starts from zero flow, so every backward arc starts at zero
+  // capacity and only the non-negative forward arcs are initially usable.
+  let mut potential = vec![0i64; vertex_count];
+
+  // push flow out of every source one at a time, each time taking the
+  // cheapest residual path to tgt (Dijkstra over Johnson-reduced costs)
+  for src in 0..vertex_count as u32 {
+    while supply[src as usize] > 0 {
+      let mut dist = vec![i64::max_value(); vertex_count];
+      let mut edge_to: Vec<Option<(usize, bool)>> = vec![None; vertex_count];
+      let mut visited = vec![false; vertex_count];
+      dist[src as usize] = 0;
+
+      let mut heap = BinaryHeap::new();
+      heap.push(cmp::Reverse((0i64, src)));
+      while let Some(cmp::Reverse((d, u))) = heap.pop() {
+        if visited[u as usize] { continue }
+        visited[u as usize] = true;
+        for &(edge_idx, forward) in out_arcs[u as usize].iter() {
+          if arc_cap(edge_idx, forward, &carried) == 0 { continue }
+          let v = arc_head(edge_idx, forward, &plan.edges);
+          let reduced_cost = arc_cost(forward) + potential[u as usize] - potential[v as usize];
+          let next_dist = d + reduced_cost;
+          if next_dist < dist[v as usize] {
+            dist[v as usize] = next_dist;
+            edge_to[v as usize] = Some((edge_idx, forward));
+            heap.push(cmp::Reverse((next_dist, v)));
+          }
+        }
+      }
+
+      if dist[tgt as usize] == i64::max_value() { break } // no augmenting path left
+      for v in 0..vertex_count {
+        if dist[v] < i64::max_value() { potential[v] += dist[v]; }
+      }
+
+      // reconstruct the path and find its bottleneck capacity
+      let mut path = Vec::new();
+      let mut vertex = tgt;
+      while vertex != src {
+        let (edge_idx, forward) = edge_to[vertex as usize].unwrap();
+        path.push((edge_idx, forward));
+        vertex = if forward { plan.edges[edge_idx].src } else { plan.edges[edge_idx].tgt };
+      }
+      path.reverse();
+
+      let mut push_amount = supply[src as usize] as u32;
+      for &(edge_idx, forward) in path.iter() {
+        push_amount = cmp::min(push_amount, arc_cap(edge_idx, forward, &carried));
+      }
+
+      for &(edge_idx, forward) in path.iter() {
+        if forward { carried[edge_idx] += push_amount; } else { carried[edge_idx] -= push_amount; }
+      }
+
+      supply[src as usize] -= push_amount as i64;
+      supply[tgt as usize] += push_amount as i64;
+    }
+  }
+
+  // write back each edge's free capacity and rerouted cargo now that this
+  // commodity's assignment has been re-derived
+  for (edge_idx, edge) in plan.edges.iter_mut().enumerate() {
+    edge.free_cap = cap[edge_idx] - carried[edge_idx];
+    edge.cargo.retain(|c| c.tgt != tgt);
+    if carried[edge_idx] > 0 {
+      edge.cargo.push(EdgeCargo { tgt, amount: carried[edge_idx] });
+    }
+  }
+
+  // re-derive the ordering constraints for commodity tgt: any edge feeding
+  // cargo for tgt into a vertex must precede whichever edge carries it onward
+  for v in 0..plan.vertex_count {
+    if v == tgt { continue }
+    let in_edges: Vec<usize> = (0..edge_count).filter(|&i|
+      plan.edges[i].tgt == v && plan.edges[i].cargo.iter().any(|c| c.tgt == tgt)).collect();
+    let out_edges: Vec<usize> = (0..edge_count).filter(|&i|
+      plan.edges[i].src == v && plan.edges[i].cargo.iter().any(|c| c.tgt == tgt)).collect();
+    for &i in in_edges.iter() {
+      for &j in out_edges.iter() {
+        // skip pairs that are already ordered either way: re-deriving a
+        // constraint that conflicts with a stage or earlier-commodity
+        // ordering would panic in Constraints::add_before, and since the
+        // flow we just pushed is already consistent with the transfer
+        // actually taking place, leaving the older ordering in place is safe
+        if i != j && !plan.constraints.is_before(i as u32, j as u32)
+          && !plan.constraints.is_before(j as u32, i as u32)
+        {
+          plan.constraints.add_before(i as u32, j as u32);
+        }
+      }
+    }
+  }
+}
+
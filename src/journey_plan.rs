@@ -1,5 +1,8 @@
 use indicatif::{ProgressBar};
-use fnv::{FnvHashSet};
+use fnv::{FnvHashSet, FnvHashMap, FnvHasher};
+use rstar::{RTree, RTreeObject, AABB, PointDistance};
+use serde::{Serialize, Deserialize};
+use std::{cmp, hash::{Hash, Hasher}};
 use crate::{constraints::{Constraints}, edge_plan::{Edge}};
 
 #[derive(Debug)]
@@ -9,14 +12,20 @@ pub struct JourneyProblem<'p> {
   pub vehicle_vertices: Vec<u32>,
   pub edges: &'p [Edge],
   pub constraints: &'p Constraints,
+  /// Coordinates of each vertex, if the problem gave them. When present,
+  /// `plan_journeys`'s jump heuristic and `restitch_journeys` prefer the
+  /// geometrically nearest candidate instead of ranking purely by
+  /// `get_available_deg`; the beam search's jump branching ranks by degree
+  /// only.
+  pub vertex_coords: Option<Vec<(f64, f64, f64)>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct JourneyPlan {
   pub legs: Vec<Vec<Leg>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Leg {
   pub vehicle: u32,
   pub src: u32,
@@ -24,6 +33,7 @@ pub struct Leg {
   pub edge_idx: Option<u32>,
 }
 
+#[derive(Clone)]
 struct State<'p> {
   problem: &'p JourneyProblem<'p>,
   stage: u32,
@@ -34,14 +44,42 @@ struct State<'p> {
   legs: Vec<Vec<Leg>>,
 }
 
+/// Selects how the jump (and, in beam mode, edge-continuation) decisions that
+/// are not forced by the available subgraph are made. Mirrors the strategy
+/// switch in `edge_plan.rs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+  /// Always take the single highest-ranked choice: edges ranked by
+  /// `get_available_deg`, jumps ranked as `find_jump` does (nearest first
+  /// when the problem has vertex coordinates, by degree otherwise).
+  Greedy,
+  /// Beam search keeping up to this many candidate states, scored by the
+  /// number of jumps emitted so far plus an admissible lower bound on the
+  /// jumps still needed. `Beam(1)` reproduces `Strategy::Greedy` exactly.
+  Beam(u32),
+}
+
 /// Plan a journey for each vehicle so that the vehicles collectively visit all
-/// edges in the problem.
+/// edges in the problem, using the given strategy.
+pub fn plan_journeys_with_strategy(
+  problem: &JourneyProblem, strategy: Strategy, bar: &ProgressBar,
+) -> JourneyPlan {
+  match strategy {
+    Strategy::Greedy => plan_journeys(problem, bar),
+    Strategy::Beam(1) => plan_journeys(problem, bar),
+    Strategy::Beam(beam_width) => plan_journeys_beam(problem, beam_width, bar),
+  }
+}
+
+/// Plan a journey for each vehicle so that the vehicles collectively visit all
+/// edges in the problem, greedily chaining the available edges.
 pub fn plan_journeys(problem: &JourneyProblem, bar: &ProgressBar) -> JourneyPlan {
   bar.reset();
   bar.set_message("planning journeys");
 
   let mut state = init_state(problem);
   let vehicle_count = problem.vehicle_vertices.len() as u32;
+  let vertex_tree = build_vertex_tree(problem);
 
   bar.set_length(problem.edges.len() as u64);
   bar.set_draw_delta((problem.edges.len()/100) as u64);
@@ -57,20 +95,12 @@ pub fn plan_journeys(problem: &JourneyProblem, bar: &ProgressBar) -> JourneyPlan
     // all vehicles are stuck in their vertices, so a vehicle must jump to
     // another vertex without following an edge
 
-    // compute the best jump target according to a heuristic
-    let jump_tgt = (0..problem.vertex_count)
-      .filter(|&vertex| !state.available_out_edges[vertex as usize].is_empty())
-      .max_by_key(|&vertex| get_available_deg(&state, vertex));
-
-    if let Some(jump_tgt) = jump_tgt {
-      // compute the best jump source (vehicle and vertex) according to a
-      // heuristic
-      let (jump_vehicle, jump_src) = (0..vehicle_count)
-        .map(|vehicle| (vehicle, state.vehicle_vertices[vehicle as usize]))
-        .min_by_key(|&(_, vertex)| get_available_deg(&state, vertex))
-        .expect("At least one vehicle is needed");
+    // compute the best jump (vehicle, source, target) according to a
+    // heuristic: nearest by Euclidean distance if the problem has
+    // coordinates (ties broken by degree), otherwise by degree alone
+    let jump = find_jump(problem, &state, vehicle_count, vertex_tree.as_ref());
 
-      // perform the jump
+    if let Some((jump_vehicle, jump_src, jump_tgt)) = jump {
       state.legs[state.stage as usize].push(Leg {
         vehicle: jump_vehicle,
         src: jump_src, tgt: jump_tgt,
@@ -208,3 +238,513 @@ fn get_available_deg(state: &State, vertex: u32) -> i32 {
 fn get_edge<'p>(state: &State<'p>, edge_idx: u32) -> &'p Edge {
   &state.problem.edges[edge_idx as usize]
 }
+
+/// A vertex positioned for `rstar`'s nearest-neighbor queries.
+struct VertexPoint {
+  vertex: u32,
+  pos: [f64; 3],
+}
+
+impl RTreeObject for VertexPoint {
+  type Envelope = AABB<[f64; 3]>;
+  fn envelope(&self) -> Self::Envelope {
+    AABB::from_point(self.pos)
+  }
+}
+
+impl PointDistance for VertexPoint {
+  fn distance_2(&self, point: &[f64; 3]) -> f64 {
+    let (dx, dy, dz) = (self.pos[0] - point[0], self.pos[1] - point[1], self.pos[2] - point[2]);
+    dx * dx + dy * dy + dz * dz
+  }
+}
+
+/// Builds an R-tree over the problem's vertex coordinates, or `None` if the
+/// problem was not given any.
+fn build_vertex_tree(problem: &JourneyProblem) -> Option<RTree<VertexPoint>> {
+  problem.vertex_coords.as_ref().map(|coords| {
+    RTree::bulk_load(coords.iter().enumerate()
+      .map(|(vertex, &(x, y, z))| VertexPoint { vertex: vertex as u32, pos: [x, y, z] })
+      .collect())
+  })
+}
+
+/// Picks the (vehicle, source vertex, target vertex) for the next forced
+/// jump. When `vertex_tree` is given, each stuck vehicle's nearest vertex
+/// with an available out edge is found via an R-tree nearest-neighbor query,
+/// and the globally closest (vehicle, target) pair wins, ties broken by
+/// `get_available_deg`. Without coordinates, falls back to picking the
+/// highest-degree target and lowest-degree source independently, as before.
+fn find_jump(
+  problem: &JourneyProblem, state: &State, vehicle_count: u32, vertex_tree: Option<&RTree<VertexPoint>>,
+) -> Option<(u32, u32, u32)> {
+  if let Some(tree) = vertex_tree {
+    (0..vehicle_count).filter_map(|vehicle| {
+      let src = state.vehicle_vertices[vehicle as usize];
+      let src_pos = vertex_pos(problem, src);
+      tree.nearest_neighbor_iter(&src_pos)
+        .find(|point| point.vertex != src && !state.available_out_edges[point.vertex as usize].is_empty())
+        .map(|point| (vehicle, src, point.vertex, point.distance_2(&src_pos)))
+    }).min_by(|a, b| a.3.partial_cmp(&b.3).unwrap()
+      .then_with(|| get_available_deg(state, b.2).cmp(&get_available_deg(state, a.2))))
+      .map(|(vehicle, src, tgt, _)| (vehicle, src, tgt))
+  } else {
+    let jump_tgt = (0..problem.vertex_count)
+      .filter(|&vertex| !state.available_out_edges[vertex as usize].is_empty())
+      .max_by_key(|&vertex| get_available_deg(state, vertex))?;
+
+    let (jump_vehicle, jump_src) = (0..vehicle_count)
+      .map(|vehicle| (vehicle, state.vehicle_vertices[vehicle as usize]))
+      .min_by_key(|&(_, vertex)| get_available_deg(state, vertex))
+      .expect("At least one vehicle is needed");
+
+    Some((jump_vehicle, jump_src, jump_tgt))
+  }
+}
+
+fn vertex_pos(problem: &JourneyProblem, vertex: u32) -> [f64; 3] {
+  let (x, y, z) = problem.vertex_coords.as_ref()
+    .expect("vertex_pos is only called when vertex_coords is Some")[vertex as usize];
+  [x, y, z]
+}
+
+/// Sums the Euclidean distance of every jump leg (a leg with no edge) in the
+/// plan. A separate metric from the jump count: a plan with fewer jumps can
+/// still travel farther overall, and vice versa. Returns 0.0 if the problem
+/// has no vertex coordinates, since distance is then not meaningful.
+pub fn total_jump_distance(plan: &JourneyPlan, problem: &JourneyProblem) -> f64 {
+  let coords = match &problem.vertex_coords {
+    Some(coords) => coords,
+    None => return 0.0,
+  };
+
+  plan.legs.iter().flatten()
+    .filter(|leg| leg.edge_idx.is_none())
+    .map(|leg| {
+      let (x1, y1, z1) = coords[leg.src as usize];
+      let (x2, y2, z2) = coords[leg.tgt as usize];
+      ((x1 - x2).powi(2) + (y1 - y2).powi(2) + (z1 - z2).powi(2)).sqrt()
+    })
+    .sum()
+}
+
+/// The number of candidates branched at each decision point of the beam
+/// search (edge continuations for the active vehicle, or jump vehicle/target
+/// pairs when all vehicles are stuck).
+const JOURNEY_BRANCH_WIDTH: u32 = 3;
+
+/// Plan journeys with a beam search that tries to minimize the number of
+/// jumps (legs with no edge), rather than greedily picking the single best
+/// continuation at each step as `plan_journeys` does.
+///
+/// A beam of up to `beam_width` candidate states is kept at all times. At
+/// each step, every live state is expanded once (either along the top
+/// `JOURNEY_BRANCH_WIDTH` edge continuations for one vehicle that can still
+/// follow an available edge, along the
+/// top `JOURNEY_BRANCH_WIDTH` x `JOURNEY_BRANCH_WIDTH` jump candidates, or
+/// deterministically by unlocking the next stage), duplicate states are
+/// dropped, and only the `beam_width` states with the lowest `f = g + h` are
+/// kept, where `g` is the number of jumps emitted so far and `h` is an
+/// admissible lower bound on the jumps still needed. Like
+/// `plan_edges_all_beam`, each candidate is a full clone of the state and `h`
+/// is recomputed from scratch at every step, so a wider beam trades
+/// noticeably more time for fewer jumps.
+fn plan_journeys_beam(problem: &JourneyProblem, beam_width: u32, bar: &ProgressBar) -> JourneyPlan {
+  assert!(beam_width >= 1);
+
+  bar.reset();
+  bar.set_message("planning journeys (beam search)");
+  bar.set_length(problem.edges.len() as u64);
+  bar.set_draw_delta((problem.edges.len()/100) as u64);
+
+  let mut beam = vec![init_state(problem)];
+  while !beam.iter().all(|state| is_complete(state) || is_stuck(state)) {
+    let mut seen = FnvHashSet::default();
+    let mut candidates = Vec::new();
+    for state in beam.drain(..) {
+      let nexts = if is_complete(&state) || is_stuck(&state) {
+        vec![state]
+      } else {
+        step_state(&state, JOURNEY_BRANCH_WIDTH)
+      };
+      for next in nexts {
+        if seen.insert(journey_fingerprint(&next)) {
+          candidates.push(next);
+        }
+      }
+    }
+
+    assert!(!candidates.is_empty(), "the beam search must always make progress");
+    candidates.sort_by_cached_key(|state| jump_count(state) + jump_lower_bound(state));
+    candidates.truncate(beam_width as usize);
+    beam = candidates;
+
+    let visited = beam.iter().map(|state| state.visited_edges.len()).max().unwrap_or(0);
+    bar.set_position(visited as u64);
+  }
+
+  // a stuck, incomplete state means the problem has no valid plan (e.g. an
+  // unreachable edge); mirror plan_journeys's assert in that case instead of
+  // silently returning a partial plan
+  let best = beam.into_iter().filter(is_complete).min_by_key(jump_count)
+    .expect("no candidate state visited all edges");
+  JourneyPlan { legs: best.legs }
+}
+
+/// Expands a single state by one decision: either an edge continuation for a
+/// vehicle that can still follow an available edge, a jump to a vertex with
+/// available edges, or unlocking the next stage. Returns the successor
+/// states (more than one only for edge continuations and jumps, which are
+/// branched over the top candidates).
+fn step_state<'p>(state: &State<'p>, branch_width: u32) -> Vec<State<'p>> {
+  if let Some(vehicle) = extendable_vehicle(state) {
+    return branch_vehicle_hop(state, vehicle, branch_width);
+  }
+
+  let has_available = (0..state.problem.vertex_count)
+    .any(|vertex| !state.available_out_edges[vertex as usize].is_empty());
+  if has_available {
+    return branch_jump(state, branch_width);
+  }
+
+  if state.stage < state.problem.stage_count {
+    let mut next = state.clone();
+    let next_stage = next.stage + 1;
+    make_stage_available(&mut next, next_stage);
+    next.stage = next_stage;
+    vec![next]
+  } else {
+    vec![state.clone()]
+  }
+}
+
+/// Returns a vehicle that is sitting in a vertex with an available outgoing
+/// edge, if any.
+fn extendable_vehicle(state: &State) -> Option<u32> {
+  (0..state.problem.vehicle_vertices.len() as u32)
+    .find(|&vehicle| !state.available_out_edges[state.vehicle_vertices[vehicle as usize] as usize].is_empty())
+}
+
+fn is_complete(state: &State) -> bool {
+  state.visited_edges.len() == state.problem.edges.len()
+}
+
+/// An incomplete state with no move left: no vehicle can extend, no vertex
+/// has an available edge to jump to, and no further stage can be unlocked.
+/// Such a state can only arise from an infeasible problem.
+fn is_stuck(state: &State) -> bool {
+  !is_complete(state)
+    && state.stage >= state.problem.stage_count
+    && extendable_vehicle(state).is_none()
+    && !(0..state.problem.vertex_count)
+      .any(|vertex| !state.available_out_edges[vertex as usize].is_empty())
+}
+
+/// Branches the given vehicle, sitting at its current vertex, over the top
+/// `branch_width` outgoing available edges, ranked like `extend_journey`
+/// ranks its single choice.
+fn branch_vehicle_hop<'p>(state: &State<'p>, vehicle: u32, branch_width: u32) -> Vec<State<'p>> {
+  let vertex = state.vehicle_vertices[vehicle as usize];
+  let mut candidates: Vec<(u32, u32)> = state.available_out_edges[vertex as usize].iter()
+    .map(|&edge_idx| (edge_idx, get_edge(state, edge_idx).tgt))
+    .collect();
+  candidates.sort_unstable_by_key(|&(_, tgt)| cmp::Reverse(get_available_deg(state, tgt)));
+  candidates.truncate(branch_width as usize);
+
+  candidates.into_iter().map(|(edge_idx, tgt)| {
+    let mut next = state.clone();
+    visit_edge(&mut next, edge_idx);
+    next.legs[next.stage as usize].push(Leg { vehicle, src: vertex, tgt, edge_idx: Some(edge_idx) });
+    next.vehicle_vertices[vehicle as usize] = tgt;
+    next
+  }).collect()
+}
+
+/// Branches over the top `branch_width` jump targets and top `branch_width`
+/// jump vehicles, ranked by degree like `plan_journeys`'s degree-only
+/// fallback. Unlike `plan_journeys`, this does not yet take `vertex_coords`
+/// into account: ranking the cross product of targets and vehicles by
+/// distance would need candidate pairs to be scored together rather than
+/// as two independent top-`branch_width` lists.
+fn branch_jump<'p>(state: &State<'p>, branch_width: u32) -> Vec<State<'p>> {
+  let vehicle_count = state.problem.vehicle_vertices.len() as u32;
+
+  let mut jump_tgts: Vec<u32> = (0..state.problem.vertex_count)
+    .filter(|&vertex| !state.available_out_edges[vertex as usize].is_empty())
+    .collect();
+  jump_tgts.sort_unstable_by_key(|&vertex| cmp::Reverse(get_available_deg(state, vertex)));
+  jump_tgts.truncate(branch_width as usize);
+
+  let mut jump_vehicles: Vec<u32> = (0..vehicle_count).collect();
+  jump_vehicles.sort_unstable_by_key(|&vehicle|
+    get_available_deg(state, state.vehicle_vertices[vehicle as usize]));
+  jump_vehicles.truncate(branch_width as usize);
+
+  let mut candidates = Vec::new();
+  for &jump_tgt in jump_tgts.iter() {
+    for &jump_vehicle in jump_vehicles.iter() {
+      let jump_src = state.vehicle_vertices[jump_vehicle as usize];
+      if jump_src == jump_tgt { continue }
+
+      let mut next = state.clone();
+      next.legs[next.stage as usize].push(Leg {
+        vehicle: jump_vehicle, src: jump_src, tgt: jump_tgt, edge_idx: None,
+      });
+      next.vehicle_vertices[jump_vehicle as usize] = jump_tgt;
+      candidates.push(next);
+    }
+  }
+  candidates
+}
+
+/// The number of jump legs (legs with no edge) emitted so far. This is `g` in
+/// the beam search scoring.
+fn jump_count(state: &State) -> u32 {
+  state.legs.iter().flatten().filter(|leg| leg.edge_idx.is_none()).count() as u32
+}
+
+/// An admissible lower bound on the number of jumps still needed (`h` in the
+/// beam search scoring): the weakly-connected components of the subgraph of
+/// not-yet-visited available edges each need at least one path-start (more,
+/// if the component's available edges are unbalanced between out- and
+/// in-degree), and a vehicle already sitting in a component can cover one of
+/// those path-starts without a jump.
+fn jump_lower_bound(state: &State) -> u32 {
+  let vertex_count = state.problem.vertex_count as usize;
+  let mut parent: Vec<usize> = (0..vertex_count).collect();
+
+  fn find(parent: &mut [usize], x: usize) -> usize {
+    if parent[x] != x { parent[x] = find(parent, parent[x]); }
+    parent[x]
+  }
+
+  let mut touched = vec![false; vertex_count];
+  for src in 0..vertex_count {
+    for &edge_idx in state.available_out_edges[src].iter() {
+      let tgt = get_edge(state, edge_idx).tgt as usize;
+      touched[src] = true;
+      touched[tgt] = true;
+      let (root_src, root_tgt) = (find(&mut parent, src), find(&mut parent, tgt));
+      if root_src != root_tgt { parent[root_src] = root_tgt; }
+    }
+  }
+
+  let mut component_demand = FnvHashMap::default();
+  for vertex in 0..vertex_count {
+    if !touched[vertex] { continue }
+    let deg = get_available_deg(state, vertex as u32);
+    let root = find(&mut parent, vertex);
+    *component_demand.entry(root).or_insert(0) += cmp::max(0, deg);
+  }
+
+  let total_path_starts: u32 = component_demand.values()
+    .map(|&demand| cmp::max(1, demand) as u32)
+    .sum();
+
+  let vehicles_in_components = state.vehicle_vertices.iter()
+    .filter(|&&vertex| touched[vertex as usize])
+    .count() as u32;
+
+  total_path_starts.saturating_sub(vehicles_in_components)
+}
+
+/// A fingerprint of the parts of a state that affect future branching:
+/// the set of visited edges, the vehicle positions and the current stage.
+fn journey_fingerprint(state: &State) -> u64 {
+  let mut visited: Vec<u32> = state.visited_edges.iter().cloned().collect();
+  visited.sort_unstable();
+
+  let mut hasher = FnvHasher::default();
+  visited.hash(&mut hasher);
+  state.vehicle_vertices.hash(&mut hasher);
+  state.stage.hash(&mut hasher);
+  hasher.finish()
+}
+
+/// A maximal single-vehicle walk between two jumps: a run of edges whose
+/// chaining (each edge's src is the previous edge's tgt) was produced by one
+/// vehicle with no intervening jump.
+#[derive(Debug, Clone)]
+struct Chain {
+  edge_indices: Vec<u32>,
+  start: u32,
+  end: u32,
+}
+
+/// Post-optimization pass over a finished `JourneyPlan`: splits each stage's
+/// legs into the maximal single-vehicle edge chains between jumps, then
+/// greedily re-stitches the chains nearest-neighbor style (possibly handing a
+/// chain to a different vehicle than originally drove it) to remove as many
+/// jumps as possible. A chain may only be placed before another if none of
+/// the other chain's edges is a `Constraints` predecessor of one of its own
+/// edges. Chains never cross the outer per-stage boundary of `legs`, so the
+/// `Edge::stage` gating already enforced when the plan was built stays
+/// satisfied without having to recheck it here. Returns the rewritten plan
+/// only if it strictly reduces the total jump count and passes a feasibility
+/// re-check (every edge still visited exactly once, in an order consistent
+/// with `Constraints`); otherwise returns the original plan unchanged.
+pub fn restitch_journeys(plan: JourneyPlan, problem: &JourneyProblem) -> JourneyPlan {
+  let mut vehicle_pos = problem.vehicle_vertices.clone();
+  let mut new_legs = Vec::with_capacity(plan.legs.len());
+
+  for stage_legs in plan.legs.iter() {
+    let chains = split_chains(stage_legs);
+    match stitch_stage(problem, &chains, vehicle_pos) {
+      Some((stitched, next_pos)) => {
+        vehicle_pos = next_pos;
+        new_legs.push(stitched);
+      }
+      // the chains require mutual interleaving (e.g. a -> d and c -> b between
+      // chains [a, b] and [c, d]) that no single chain ordering can satisfy;
+      // give up on this stage and keep the original plan
+      None => return plan,
+    }
+  }
+
+  let rewritten = JourneyPlan { legs: new_legs };
+  if plan_jump_count(&rewritten) < plan_jump_count(&plan) && check_feasible(&rewritten, problem) {
+    rewritten
+  } else {
+    plan
+  }
+}
+
+/// Splits a single stage's leg sequence into its maximal chains, discarding
+/// the jump legs (they are re-derived by `stitch_stage` as needed).
+fn split_chains(legs: &[Leg]) -> Vec<Chain> {
+  let mut chains = Vec::new();
+  let mut current: Option<(u32, Chain)> = None;
+
+  for leg in legs {
+    let continues_current = current.as_ref().map_or(false, |(vehicle, chain)|
+      *vehicle == leg.vehicle && chain.end == leg.src);
+
+    match leg.edge_idx {
+      Some(edge_idx) if continues_current => {
+        let (_, chain) = current.as_mut().unwrap();
+        chain.edge_indices.push(edge_idx);
+        chain.end = leg.tgt;
+      }
+      Some(edge_idx) => {
+        if let Some((_, chain)) = current.take() { chains.push(chain); }
+        current = Some((leg.vehicle, Chain {
+          edge_indices: vec![edge_idx], start: leg.src, end: leg.tgt,
+        }));
+      }
+      None => {
+        if let Some((_, chain)) = current.take() { chains.push(chain); }
+      }
+    }
+  }
+  if let Some((_, chain)) = current.take() { chains.push(chain); }
+
+  chains
+}
+
+/// Greedily re-stitches one stage's chains starting from `vehicle_pos`,
+/// returning the rewritten legs and the vehicle positions at the end of the
+/// stage (the starting point for the next stage, if any), or `None` if the
+/// chains have no valid total order (some pair requires mutual interleaving
+/// that a chain-level ordering can't express).
+fn stitch_stage(
+  problem: &JourneyProblem, chains: &[Chain], mut vehicle_pos: Vec<u32>,
+) -> Option<(Vec<Leg>, Vec<u32>)> {
+  let vehicle_count = vehicle_pos.len();
+  let mut remaining: Vec<usize> = (0..chains.len()).collect();
+  let mut legs = Vec::new();
+
+  while !remaining.is_empty() {
+    let zero_jump_match = (0..vehicle_count).find_map(|vehicle| {
+      remaining.iter().position(|&chain_idx|
+        chains[chain_idx].start == vehicle_pos[vehicle] &&
+        is_eligible_chain(problem, chains, &remaining, chain_idx))
+        .map(|remaining_pos| (vehicle, remaining_pos))
+    });
+
+    let (vehicle, remaining_pos) = match zero_jump_match {
+      Some(found) => found,
+      None => {
+        let remaining_pos = earliest_remaining_chain(problem, chains, &remaining)?;
+        let chain_start = chains[remaining[remaining_pos]].start;
+        (nearest_vehicle(problem, &vehicle_pos, chain_start), remaining_pos)
+      }
+    };
+
+    let chain = &chains[remaining.remove(remaining_pos)];
+
+    if vehicle_pos[vehicle] != chain.start {
+      legs.push(Leg { vehicle: vehicle as u32, src: vehicle_pos[vehicle], tgt: chain.start, edge_idx: None });
+    }
+    for &edge_idx in chain.edge_indices.iter() {
+      let edge = &problem.edges[edge_idx as usize];
+      legs.push(Leg { vehicle: vehicle as u32, src: edge.src, tgt: edge.tgt, edge_idx: Some(edge_idx) });
+    }
+    vehicle_pos[vehicle] = chain.end;
+  }
+
+  Some((legs, vehicle_pos))
+}
+
+/// Picks the vehicle closest to `target` by Euclidean distance, if the
+/// problem has vertex coordinates; otherwise always vehicle 0, since there is
+/// then no distance metric to compare candidates with.
+fn nearest_vehicle(problem: &JourneyProblem, vehicle_pos: &[u32], target: u32) -> usize {
+  let coords = match &problem.vertex_coords {
+    Some(coords) => coords,
+    None => return 0,
+  };
+
+  let (tx, ty, tz) = coords[target as usize];
+  (0..vehicle_pos.len()).min_by(|&a, &b| {
+    let dist_2 = |v: usize| {
+      let (x, y, z) = coords[vehicle_pos[v] as usize];
+      (x - tx).powi(2) + (y - ty).powi(2) + (z - tz).powi(2)
+    };
+    dist_2(a).partial_cmp(&dist_2(b)).unwrap()
+  }).expect("at least one vehicle is needed")
+}
+
+/// Finds a remaining chain (by position in `remaining`) that has no unplaced
+/// predecessor among the other remaining chains, i.e. one that is free to go
+/// next even though it requires a jump. Returns `None` if every remaining
+/// chain has an unplaced predecessor, which means the chains require mutual
+/// interleaving that no single ordering of whole chains can satisfy.
+fn earliest_remaining_chain(problem: &JourneyProblem, chains: &[Chain], remaining: &[usize]) -> Option<usize> {
+  (0..remaining.len()).find(|&pos| is_eligible_chain(problem, chains, remaining, remaining[pos]))
+}
+
+/// Decides whether the given chain (identified by its index into `chains`)
+/// has no unplaced predecessor among the other chains still in `remaining`,
+/// i.e. whether it may be placed next regardless of which vehicle carries it.
+fn is_eligible_chain(problem: &JourneyProblem, chains: &[Chain], remaining: &[usize], chain_idx: usize) -> bool {
+  remaining.iter().all(|&other_idx|
+    other_idx == chain_idx || chain_can_precede(problem.constraints, &chains[chain_idx], &chains[other_idx]))
+}
+
+/// Decides whether chain `a` may be placed before chain `b`: no edge of `b`
+/// may be a `Constraints` predecessor of an edge of `a`.
+fn chain_can_precede(constraints: &Constraints, a: &Chain, b: &Chain) -> bool {
+  !a.edge_indices.iter().any(|&a_edge|
+    b.edge_indices.iter().any(|&b_edge| constraints.is_before(b_edge, a_edge)))
+}
+
+/// The total number of jump legs (legs with no edge) in the plan.
+fn plan_jump_count(plan: &JourneyPlan) -> usize {
+  plan.legs.iter().flatten().filter(|leg| leg.edge_idx.is_none()).count()
+}
+
+/// Re-checks that the plan visits every edge exactly once, in an order
+/// consistent with `Constraints`.
+fn check_feasible(plan: &JourneyPlan, problem: &JourneyProblem) -> bool {
+  let mut visited = FnvHashSet::default();
+  for leg in plan.legs.iter().flatten() {
+    if let Some(edge_idx) = leg.edge_idx {
+      let predecessors_visited = problem.constraints.predecessors(edge_idx)
+        .all(|pred_idx| visited.contains(&pred_idx));
+      if !predecessors_visited || !visited.insert(edge_idx) {
+        return false;
+      }
+    }
+  }
+  visited.len() == problem.edges.len()
+}
@@ -1,10 +1,15 @@
 extern crate array2d;
+extern crate bincode;
 #[macro_use] extern crate clap;
 extern crate fnv;
 extern crate indicatif;
 extern crate rayon;
+extern crate rstar;
+extern crate serde;
+extern crate sha3;
 
 mod bit_mat;
+mod cache;
 mod constraints;
 mod edge_plan;
 mod journey_plan;
@@ -15,11 +20,20 @@ mod write;
 use array2d::{Array2D};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::{fs, io, sync::{Arc}, thread, time};
+use serde::{Serialize, Deserialize};
+use std::{fs, io, io::Read as _, path::PathBuf, sync::{Arc}, thread, time};
 use crate::{
-  edge_plan::{init_edge_state, plan_edges, plan_edges_hub, plan_edges_all},
-  journey_plan::{JourneyProblem, plan_journeys},
-  parcel_plan::{ParcelProblem, Action, plan_parcels},
+  constraints::{Constraints},
+  edge_plan::{
+    init_edge_state, plan_edges, plan_edges_hub, plan_edges_all_with_strategy,
+    reroute_min_cost_flow, Edge, Strategy, VehicleClass,
+  },
+  journey_plan::{
+    JourneyProblem, Strategy as JourneyStrategy,
+    plan_journeys_with_strategy, restitch_journeys, total_jump_distance,
+  },
+  parcel_plan::{ParcelProblem, Action, build_action_deps, plan_parcels},
+  write::{DepOptions},
 };
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -29,12 +43,107 @@ struct VehicleConfig {
   cap: u32,
   transfer_cost: u64,
   go_cost: u64,
+  /// Additional vehicle classes sharing this fleet's transfer_cost but with
+  /// their own cap and go_cost, for mixed fleets (e.g. vans alongside
+  /// trucks). Populated only from a config file's repeated `extra_class`
+  /// lines; `classes()` always includes the base (cap, go_cost) as well.
+  extra_classes: Vec<VehicleClass>,
+}
+
+impl VehicleConfig {
+  fn classes(&self) -> Vec<VehicleClass> {
+    let mut classes = vec![VehicleClass { cap: self.cap, go_cost: self.go_cost }];
+    classes.extend(self.extra_classes.iter().cloned());
+    classes
+  }
 }
 
 #[derive(Debug)]
 struct Config {
   truck: VehicleConfig,
   airplane: VehicleConfig,
+  strategy: Strategy,
+  journey_strategy: JourneyStrategy,
+  reroute: bool,
+  restitch: bool,
+  /// When set, a plan is cached here keyed by a digest of the input, so a
+  /// repeated run of the same problem can skip planning entirely.
+  cache_dir: Option<PathBuf>,
+  /// When set, each emitted action is annotated with the actions (among
+  /// `plan.action_deps`'s transitive reduction) it directly depends on.
+  annotate_deps: bool,
+  /// When set (implies `annotate_deps`), actions are written in a
+  /// `plan.action_deps`-respecting order instead of their original plan order.
+  reorder_deps: bool,
+}
+
+/// Fleet settings read from a config file, applied on top of the built-in
+/// defaults before the per-flag CLI overrides.
+#[derive(Debug, Default)]
+struct ConfigOverrides {
+  truck_cap: Option<u32>,
+  truck_go_cost: Option<u64>,
+  truck_transfer_cost: Option<u64>,
+  airplane_cap: Option<u32>,
+  airplane_go_cost: Option<u64>,
+  airplane_transfer_cost: Option<u64>,
+  truck_extra_classes: Vec<VehicleClass>,
+  airplane_extra_classes: Vec<VehicleClass>,
+}
+
+impl ConfigOverrides {
+  fn apply(&self, cfg: &mut Config) {
+    if let Some(v) = self.truck_cap { cfg.truck.cap = v }
+    if let Some(v) = self.truck_go_cost { cfg.truck.go_cost = v }
+    if let Some(v) = self.truck_transfer_cost { cfg.truck.transfer_cost = v }
+    if let Some(v) = self.airplane_cap { cfg.airplane.cap = v }
+    if let Some(v) = self.airplane_go_cost { cfg.airplane.go_cost = v }
+    if let Some(v) = self.airplane_transfer_cost { cfg.airplane.transfer_cost = v }
+    cfg.truck.extra_classes.extend(self.truck_extra_classes.iter().cloned());
+    cfg.airplane.extra_classes.extend(self.airplane_extra_classes.iter().cloned());
+  }
+}
+
+/// Parses a "cap,go_cost" pair, as used by the `extra_class` config keys.
+fn parse_vehicle_class(value: &str) -> Result<VehicleClass> {
+  let mut parts = value.splitn(2, ',');
+  let cap = parts.next().ok_or("Expected cap,go_cost")?.trim().parse()?;
+  let go_cost = parts.next().ok_or("Expected cap,go_cost")?.trim().parse()?;
+  Ok(VehicleClass { cap, go_cost })
+}
+
+/// Reads fleet overrides from a config file made of flat `key = value` lines
+/// with dotted keys such as `truck.cap`, so a user can model a different
+/// problem instance (caps, go_cost, transfer_cost) without recompiling.
+/// Blank lines and lines starting with `#` are skipped; sections, quoted
+/// values and JSON are not parsed. `extra_class` keys may repeat to add more
+/// vehicle classes to a mixed fleet.
+fn read_config_file(path: &std::ffi::OsStr) -> Result<ConfigOverrides> {
+  let contents = fs::read_to_string(path)?;
+  let mut overrides = ConfigOverrides::default();
+
+  for line in contents.lines() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') { continue }
+
+    let mut parts = line.splitn(2, '=');
+    let key = parts.next().ok_or("Expected key = value")?.trim();
+    let value = parts.next().ok_or("Expected key = value")?.trim();
+
+    match key {
+      "truck.cap" => overrides.truck_cap = Some(value.parse()?),
+      "truck.go_cost" => overrides.truck_go_cost = Some(value.parse()?),
+      "truck.transfer_cost" => overrides.truck_transfer_cost = Some(value.parse()?),
+      "truck.extra_class" => overrides.truck_extra_classes.push(parse_vehicle_class(value)?),
+      "airplane.cap" => overrides.airplane_cap = Some(value.parse()?),
+      "airplane.go_cost" => overrides.airplane_go_cost = Some(value.parse()?),
+      "airplane.transfer_cost" => overrides.airplane_transfer_cost = Some(value.parse()?),
+      "airplane.extra_class" => overrides.airplane_extra_classes.push(parse_vehicle_class(value)?),
+      _ => return Err(format!("Unknown config key: {}", key))?,
+    }
+  }
+
+  Ok(overrides)
 }
 
 #[derive(Debug)]
@@ -45,13 +154,19 @@ pub struct Problem {
   pub parcel_count: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Plan {
   pub truck_actions_1: Vec<Action>,
   pub airplane_actions_2: Vec<Action>,
   pub truck_actions_3: Vec<Action>,
   pub cost: u64,
   pub min_cost: u64,
+  /// Total Euclidean distance of all jump legs, across cities and airplanes.
+  /// 0.0 unless the input gave vertex coordinates.
+  pub jump_distance: f64,
+  /// Dependencies among the actions of `truck_actions_1 ++ airplane_actions_2
+  /// ++ truck_actions_3` (in that order), as used by `write::DepOptions`.
+  pub action_deps: Constraints,
 }
 
 #[derive(Debug)]
@@ -64,14 +179,20 @@ pub struct CityProblem {
   pub outbound_parcel_ids: Vec<Vec<u32>>,
   pub inbound_parcel_ids: Vec<Vec<u32>>,
   pub parcel_count: u32,
+  /// Coordinates of each depo in `depo_ids`, in the same order. `None` unless
+  /// the input gave coordinates for every depo in the whole problem.
+  pub depo_coords: Option<Vec<(f64, f64, f64)>>,
 }
 
 #[derive(Debug)]
 struct CityPlan {
   before_air_actions: Vec<Action>,
   after_air_actions: Vec<Action>,
+  before_air_deps: Constraints,
+  after_air_deps: Constraints,
   cost: u64,
   min_cost: u64,
+  jump_distance: f64,
 }
 
 #[derive(Debug)]
@@ -79,13 +200,18 @@ pub struct AirProblem {
   pub airport_ids: Vec<u32>,
   pub airplane_airports: Vec<u32>,
   pub parcel_ids: Array2D<Vec<u32>>,
+  /// Coordinates of each airport in `airport_ids`, in the same order. `None`
+  /// unless the input gave coordinates for every depo in the whole problem.
+  pub airport_coords: Option<Vec<(f64, f64, f64)>>,
 }
 
 #[derive(Debug)]
 struct AirPlan {
   air_actions: Vec<Action>,
+  air_deps: Constraints,
   cost: u64,
   min_cost: u64,
+  jump_distance: f64,
 }
 
 
@@ -99,59 +225,185 @@ fn main() -> Result<()> {
     (about: crate_description!())
     (@arg INPUT: +required "Input file with problem defition (use - for stdin)")
     (@arg OUTPUT: +required "Output file with problem solution (use - for stdout)")
+    (@arg config: --config +takes_value
+      "Config file with fleet overrides (flat `key = value` lines)")
+    (@arg strategy: --strategy +takes_value
+      "Edge-planning strategy: greedy, astar, or beam (default: astar)")
+    (@arg beam_width: --("beam-width") +takes_value
+      "Beam width used when --strategy=beam (default: 4)")
+    (@arg journey_strategy: --("journey-strategy") +takes_value
+      "Journey-planning strategy: greedy or beam (default: greedy)")
+    (@arg journey_beam_width: --("journey-beam-width") +takes_value
+      "Beam width used when --journey-strategy=beam (default: 4)")
+    (@arg reroute: --reroute
+      "Re-route planned edges with a min-cost flow pass to reduce transfers")
+    (@arg restitch: --restitch
+      "Re-stitch planned journeys with a local search pass to remove jumps")
+    (@arg annotate_deps: --("annotate-deps")
+      "Annotate each written action with the actions it directly depends on")
+    (@arg reorder_deps: --("reorder-deps")
+      "Write actions in dependency order instead of plan order (implies --annotate-deps)")
+    (@arg cache_dir: --("cache-dir") +takes_value
+      "Cache plans in this directory, keyed by a digest of the input")
+    (@arg truck_cap: --("truck-cap") +takes_value "Truck capacity override")
+    (@arg truck_go_cost: --("truck-go-cost") +takes_value "Truck go_cost override")
+    (@arg truck_transfer_cost: --("truck-transfer-cost") +takes_value "Truck transfer_cost override")
+    (@arg airplane_cap: --("airplane-cap") +takes_value "Airplane capacity override")
+    (@arg airplane_go_cost: --("airplane-go-cost") +takes_value "Airplane go_cost override")
+    (@arg airplane_transfer_cost: --("airplane-transfer-cost") +takes_value "Airplane transfer_cost override")
   ).get_matches();
   let input_path = args.value_of_os("INPUT").unwrap();
   let output_path = args.value_of_os("OUTPUT").unwrap();
 
-  let cfg = Config {
-    truck: VehicleConfig { cap: 4, transfer_cost: 2+2, go_cost: 17 },
-    airplane: VehicleConfig { cap: 30, transfer_cost: 14+11, go_cost: 1000 },
+  let mut cfg = Config {
+    truck: VehicleConfig { cap: 4, transfer_cost: 2+2, go_cost: 17, extra_classes: Vec::new() },
+    airplane: VehicleConfig { cap: 30, transfer_cost: 14+11, go_cost: 1000, extra_classes: Vec::new() },
+    strategy: Strategy::AStar,
+    journey_strategy: JourneyStrategy::Greedy,
+    reroute: false,
+    restitch: false,
+    cache_dir: None,
+    annotate_deps: false,
+    reorder_deps: false,
   };
 
-  // read problem from file or stdin
-  let problem = {
-    let bar = ProgressBar::new_spinner().with_style(spinner_style());
-    bar.set_prefix("Input");
-    let problem = if input_path != "-" {
-      let file = fs::File::open(input_path)?;
-      let mut input = io::BufReader::new(file);
-      read::read_problem(&mut input, &bar)?
+  if let Some(config_path) = args.value_of_os("config") {
+    read_config_file(config_path)?.apply(&mut cfg);
+  }
+
+  if let Some(v) = args.value_of("truck_cap") { cfg.truck.cap = v.parse()?; }
+  if let Some(v) = args.value_of("truck_go_cost") { cfg.truck.go_cost = v.parse()?; }
+  if let Some(v) = args.value_of("truck_transfer_cost") { cfg.truck.transfer_cost = v.parse()?; }
+  if let Some(v) = args.value_of("airplane_cap") { cfg.airplane.cap = v.parse()?; }
+  if let Some(v) = args.value_of("airplane_go_cost") { cfg.airplane.go_cost = v.parse()?; }
+  if let Some(v) = args.value_of("airplane_transfer_cost") { cfg.airplane.transfer_cost = v.parse()?; }
+
+  let beam_width: u32 = args.value_of("beam_width")
+    .map(|s| s.parse()).transpose()?.unwrap_or(4);
+  cfg.strategy = match args.value_of("strategy") {
+    None | Some("astar") => Strategy::AStar,
+    Some("greedy") => Strategy::Greedy,
+    Some("beam") => Strategy::Beam(beam_width),
+    Some(other) => return Err(format!("Unknown strategy: {}", other))?,
+  };
+  let journey_beam_width: u32 = args.value_of("journey_beam_width")
+    .map(|s| s.parse()).transpose()?.unwrap_or(4);
+  cfg.journey_strategy = match args.value_of("journey_strategy") {
+    None | Some("greedy") => JourneyStrategy::Greedy,
+    Some("beam") => JourneyStrategy::Beam(journey_beam_width),
+    Some(other) => return Err(format!("Unknown journey strategy: {}", other))?,
+  };
+  cfg.reroute = cfg.reroute || args.is_present("reroute");
+  cfg.restitch = cfg.restitch || args.is_present("restitch");
+  cfg.cache_dir = args.value_of_os("cache_dir").map(PathBuf::from);
+  cfg.reorder_deps = args.is_present("reorder_deps");
+  cfg.annotate_deps = cfg.reorder_deps || args.is_present("annotate_deps");
+
+  // with caching enabled we need the whole input in memory to hash it before
+  // parsing, and to re-parse it from the buffer on a miss; without caching we
+  // stream straight into the parser as before, so the common case does not
+  // pay for an extra copy of the input
+  let input_bytes = if cfg.cache_dir.is_some() {
+    Some(if input_path != "-" {
+      fs::read(input_path)?
     } else {
-      let stdin = io::stdin();
-      let mut input = stdin.lock();
-      read::read_problem(&mut input, &bar)?
-    };
-    bar.finish_and_clear();
-    problem
+      let mut buf = Vec::new();
+      io::stdin().lock().read_to_end(&mut buf)?;
+      buf
+    })
+  } else {
+    None
+  };
+  // everything that can change the resulting plan besides the input bytes
+  // themselves, so that differently-configured runs never share a cache entry
+  let cfg_repr = format!("{:?}|{:?}|{:?}|{:?}|{}|{}",
+    cfg.truck, cfg.airplane, cfg.strategy, cfg.journey_strategy, cfg.reroute, cfg.restitch);
+  let cache_digest = input_bytes.as_ref().map(|bytes| cache::digest(bytes, &cfg_repr));
+  let cached_plan = match (&cfg.cache_dir, &cache_digest) {
+    (Some(dir), Some(digest)) => cache::read_cached_plan(dir, digest),
+    _ => None,
   };
 
-  let parcel_count = problem.parcel_count;
-  eprintln!("Problem has {} cities, {} depos, {} parcels",
-    problem.cities.len(), problem.depos.len(), problem.parcel_count);
-
-  // solve the problem
-  let plan = {
-    let plan = solve_problem(problem, &cfg);
-
-    eprintln!("Plan cost {}, min cost {} (gap <= {:.3}), avg {:.2} per parcel",
-      plan.cost, plan.min_cost,
-      plan.cost as f64 / plan.min_cost as f64 - 1.0,
-      plan.cost as f64 / parcel_count as f64);
-    plan
+  let plan = match cached_plan {
+    Some(plan) => {
+      eprintln!("Using cached plan (digest {})", cache_digest.as_ref().unwrap());
+      eprintln!("Plan cost {}, min cost {} (gap <= {:.3})",
+        plan.cost, plan.min_cost, plan.cost as f64 / plan.min_cost as f64 - 1.0);
+      if plan.jump_distance > 0.0 {
+        eprintln!("Total jump distance {:.2}", plan.jump_distance);
+      }
+      plan
+    }
+    None => {
+      // read problem from the buffered input if we have one (caching is
+      // enabled), otherwise stream straight from the file or stdin
+      let problem = {
+        let bar = ProgressBar::new_spinner().with_style(spinner_style());
+        bar.set_prefix("Input");
+        let problem = match &input_bytes {
+          Some(bytes) => {
+            let mut input = io::Cursor::new(bytes);
+            read::read_problem(&mut input, &bar)?
+          }
+          None if input_path != "-" => {
+            let file = fs::File::open(input_path)?;
+            let mut input = io::BufReader::new(file);
+            read::read_problem(&mut input, &bar)?
+          }
+          None => {
+            let stdin = io::stdin();
+            let mut input = stdin.lock();
+            read::read_problem(&mut input, &bar)?
+          }
+        };
+        bar.finish_and_clear();
+        problem
+      };
+
+      let parcel_count = problem.parcel_count;
+      eprintln!("Problem has {} cities, {} depos, {} parcels",
+        problem.cities.len(), problem.depos.len(), problem.parcel_count);
+
+      // solve the problem
+      let plan = solve_problem(problem, &cfg);
+
+      eprintln!("Plan cost {}, min cost {} (gap <= {:.3}), avg {:.2} per parcel",
+        plan.cost, plan.min_cost,
+        plan.cost as f64 / plan.min_cost as f64 - 1.0,
+        plan.cost as f64 / parcel_count as f64);
+      if plan.jump_distance > 0.0 {
+        eprintln!("Total jump distance {:.2}", plan.jump_distance);
+      }
+
+      if let (Some(dir), Some(digest)) = (&cfg.cache_dir, &cache_digest) {
+        // caching is a best-effort speed-up: a write failure (e.g. a full or
+        // read-only cache directory) should not throw away a plan we already
+        // paid to compute
+        if let Err(err) = cache::write_cached_plan(dir, digest, &plan) {
+          eprintln!("Warning: failed to write plan cache: {}", err);
+        }
+      }
+      plan
+    }
   };
 
   // write the plan to file or stdout
   {
     let bar = ProgressBar::new_spinner().with_style(spinner_style());
     bar.set_prefix("Output");
+    let deps = if cfg.annotate_deps {
+      Some(DepOptions { constraints: &plan.action_deps, reorder: cfg.reorder_deps })
+    } else {
+      None
+    };
     if output_path != "-" {
       let file = fs::File::create(output_path)?;
       let mut output = io::BufWriter::new(file);
-      write::write_plan(&mut output, &plan, &bar)?;
+      write::write_plan(&mut output, &plan, &bar, deps.as_ref())?;
     } else {
       let stdout = io::stdout();
       let mut output = io::BufWriter::new(stdout.lock());
-      write::write_plan(&mut output, &plan, &bar)?;
+      write::write_plan(&mut output, &plan, &bar, deps.as_ref())?;
     }
     bar.finish_and_clear();
   };
@@ -185,8 +437,11 @@ fn solve_problem(problem: Problem, cfg: &Config) -> Plan {
     },
     move || {
       cities_bar.set_prefix("Cities   ");
-      let plans = city_problems.into_iter().enumerate()
-        .par_bridge()
+      // city_problems is already a Vec, so into_par_iter() gives an indexed
+      // parallel iterator: collect() below reassembles results in the
+      // original city order regardless of which worker finishes first,
+      // unlike par_bridge() (which drops that ordering guarantee).
+      let plans = city_problems.into_par_iter().enumerate()
         .map(|(i, city_problem)| {
           let city_bar =
             if city_problem.parcel_count >= 1000 {
@@ -209,16 +464,43 @@ fn solve_problem(problem: Problem, cfg: &Config) -> Plan {
 
   let cost = city_plans.iter().map(|p| p.cost).sum::<u64>() + air_plan.cost;
   let min_cost = city_plans.iter().map(|p| p.min_cost).sum::<u64>() + air_plan.min_cost;
+  let jump_distance =
+    city_plans.iter().map(|p| p.jump_distance).sum::<f64>() + air_plan.jump_distance;
 
+  // concatenate the action groups, remembering where each city's before/after
+  // segment landed so its own action-indexed deps can be shifted into place
+  // in the merged action_deps below
   let mut truck_actions_1 = Vec::new();
   let mut truck_actions_3 = Vec::new();
+  let mut before_segments = Vec::new();
+  let mut after_segments = Vec::new();
   for city_plan in city_plans {
+    before_segments.push((truck_actions_1.len() as u32, city_plan.before_air_deps));
     truck_actions_1.extend(city_plan.before_air_actions);
+    after_segments.push((truck_actions_3.len() as u32, city_plan.after_air_deps));
     truck_actions_3.extend(city_plan.after_air_actions);
   }
 
   let airplane_actions_2 = air_plan.air_actions;
-  Plan { truck_actions_1, airplane_actions_2, truck_actions_3, cost, min_cost }
+  let airplane_offset = truck_actions_1.len() as u32;
+  let truck_3_offset = airplane_offset + airplane_actions_2.len() as u32;
+
+  let total_actions =
+    truck_actions_1.len() + airplane_actions_2.len() + truck_actions_3.len();
+  let mut action_deps = Constraints::with_capacity(total_actions);
+  for _ in 0..total_actions { action_deps.push(); }
+  for (offset, deps) in &before_segments {
+    action_deps.add_shifted_from(deps, *offset);
+  }
+  action_deps.add_shifted_from(&air_plan.air_deps, airplane_offset);
+  for (offset, deps) in &after_segments {
+    action_deps.add_shifted_from(deps, truck_3_offset + *offset);
+  }
+
+  Plan {
+    truck_actions_1, airplane_actions_2, truck_actions_3,
+    cost, min_cost, jump_distance, action_deps,
+  }
 }
 
 fn solve_city_problem(problem: CityProblem, cfg: &Config, bar: &ProgressBar) -> CityPlan {
@@ -240,23 +522,28 @@ fn solve_city_problem(problem: CityProblem, cfg: &Config, bar: &ProgressBar) ->
     depo_count, depo_count);
 
   // plan the edges
-  let mut edge_state = init_edge_state(depo_count as u32, cfg.truck.cap, p_mat);
+  let mut edge_state = init_edge_state(depo_count as u32, cfg.truck.classes(), p_mat);
   plan_edges_hub(&mut edge_state, airport as u32, &bar);
-  plan_edges_all(&mut edge_state, &bar);
-  let edge_plan = plan_edges(edge_state);
+  plan_edges_all_with_strategy(&mut edge_state, cfg.strategy, &bar);
+  let mut edge_plan = plan_edges(edge_state);
+  if cfg.reroute { reroute_min_cost_flow(&mut edge_plan); }
 
   let min_cost =
-    edge_plan.min_edge_count as u64 * cfg.truck.go_cost +
+    edge_plan.min_go_cost +
     edge_plan.parcel_count as u64 * cfg.truck.transfer_cost;
 
   // plan the journeys
-  let journey_plan = plan_journeys(&JourneyProblem {
+  let journey_problem = JourneyProblem {
     vertex_count: depo_count as u32,
     stage_count: 2,
     vehicle_vertices: problem.truck_depos,
     edges: &edge_plan.edges,
     constraints: &edge_plan.constraints,
-  }, &bar);
+    vertex_coords: problem.depo_coords,
+  };
+  let mut journey_plan = plan_journeys_with_strategy(&journey_problem, cfg.journey_strategy, &bar);
+  if cfg.restitch { journey_plan = restitch_journeys(journey_plan, &journey_problem); }
+  let jump_distance = total_jump_distance(&journey_plan, &journey_problem);
 
   // plan the parcels
   let parcel_plan = plan_parcels(&ParcelProblem {
@@ -271,11 +558,17 @@ fn solve_city_problem(problem: CityProblem, cfg: &Config, bar: &ProgressBar) ->
   assert_eq!(actions.len(), 2);
   let after_air_actions = actions.pop().unwrap();
   let before_air_actions = actions.pop().unwrap();
-  let cost = sum_cost(&after_air_actions, &cfg.truck) +
-    sum_cost(&before_air_actions, &cfg.truck);
+  let cost = sum_cost(&after_air_actions, &edge_plan.edges, &cfg.truck) +
+    sum_cost(&before_air_actions, &edge_plan.edges, &cfg.truck);
+
+  let before_air_deps = build_action_deps(&edge_plan.constraints, &before_air_actions);
+  let after_air_deps = build_action_deps(&edge_plan.constraints, &after_air_actions);
 
   bar.finish_and_clear();
-  CityPlan { before_air_actions, after_air_actions, cost, min_cost }
+  CityPlan {
+    before_air_actions, after_air_actions, before_air_deps, after_air_deps,
+    cost, min_cost, jump_distance,
+  }
 }
 
 fn solve_air_problem(problem: AirProblem, cfg: &Config, bar: &ProgressBar) -> AirPlan {
@@ -289,21 +582,26 @@ fn solve_air_problem(problem: AirProblem, cfg: &Config, bar: &ProgressBar) -> Ai
     parcel_ids.elements_row_major_iter().map(|ids| ids.len() as u32),
     airport_count, airport_count);
 
-  let mut edge_state = init_edge_state(airport_count as u32, cfg.airplane.cap, p_mat);
-  plan_edges_all(&mut edge_state, &bar);
-  let edge_plan = plan_edges(edge_state);
+  let mut edge_state = init_edge_state(airport_count as u32, cfg.airplane.classes(), p_mat);
+  plan_edges_all_with_strategy(&mut edge_state, cfg.strategy, &bar);
+  let mut edge_plan = plan_edges(edge_state);
+  if cfg.reroute { reroute_min_cost_flow(&mut edge_plan); }
 
   let min_cost =
-    edge_plan.min_edge_count as u64 * cfg.airplane.go_cost +
+    edge_plan.min_go_cost +
     edge_plan.parcel_count as u64 * cfg.airplane.transfer_cost;
 
-  let journey_plan = plan_journeys(&JourneyProblem {
+  let journey_problem = JourneyProblem {
     vertex_count: airport_count as u32,
     stage_count: 1,
     vehicle_vertices: problem.airplane_airports,
     edges: &edge_plan.edges,
     constraints: &edge_plan.constraints,
-  }, &bar);
+    vertex_coords: problem.airport_coords,
+  };
+  let mut journey_plan = plan_journeys_with_strategy(&journey_problem, cfg.journey_strategy, &bar);
+  if cfg.restitch { journey_plan = restitch_journeys(journey_plan, &journey_problem); }
+  let jump_distance = total_jump_distance(&journey_plan, &journey_problem);
 
   let airplane_ids: Vec<_> = (0..airplane_count).collect();
   let parcel_plan = plan_parcels(&ParcelProblem {
@@ -317,14 +615,21 @@ fn solve_air_problem(problem: AirProblem, cfg: &Config, bar: &ProgressBar) -> Ai
   let mut actions = parcel_plan.actions;
   assert_eq!(actions.len(), 1);
   let air_actions = actions.pop().unwrap();
-  let cost = sum_cost(&air_actions, &cfg.airplane);
+  let cost = sum_cost(&air_actions, &edge_plan.edges, &cfg.airplane);
+  let air_deps = build_action_deps(&edge_plan.constraints, &air_actions);
 
-  AirPlan { air_actions, cost, min_cost }
+  AirPlan { air_actions, air_deps, cost, min_cost, jump_distance }
 }
 
-fn sum_cost(actions: &[Action], vehicle: &VehicleConfig) -> u64 {
+/// Sums the realized cost of a vehicle's actions. A Go that follows a planned
+/// edge (`edge_idx: Some(_)`) is charged that edge's own `vehicle_class.go_cost`,
+/// since a mixed fleet's edges are not all planned with the same class; a Go
+/// that jumps without following any edge (`edge_idx: None`) falls back to the
+/// fleet's base `go_cost`.
+fn sum_cost(actions: &[Action], edges: &[Edge], vehicle: &VehicleConfig) -> u64 {
   actions.iter().map(|a| match a {
-    Action::Go { .. } => vehicle.go_cost,
+    Action::Go { edge_idx: Some(edge_idx), .. } => edges[*edge_idx as usize].vehicle_class.go_cost,
+    Action::Go { edge_idx: None, .. } => vehicle.go_cost,
     Action::Load { .. } => 0,
     Action::Unload { .. } => vehicle.transfer_cost,
   }).sum()
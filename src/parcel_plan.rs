@@ -1,7 +1,8 @@
 use array2d::{Array2D};
 use indicatif::{ProgressBar};
 use fnv::{FnvHashMap, FnvHashSet};
-use crate::{edge_plan::{Edge}, journey_plan::{Leg}};
+use serde::{Serialize, Deserialize};
+use crate::{edge_plan::{Edge}, journey_plan::{Leg}, constraints::{Constraints}};
 
 #[derive(Debug)]
 pub struct ParcelProblem<'p> {
@@ -12,14 +13,17 @@ pub struct ParcelProblem<'p> {
   pub parcel_ids: &'p Array2D<Vec<u32>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ParcelPlan {
   pub actions: Vec<Vec<Action>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
-  Go { vehicle_id: u32, src_id: u32, tgt_id: u32 },
+  /// `edge_idx` is the `Edge` (and thus the `VehicleClass`/`go_cost`) this Go
+  /// was planned along, or `None` for a jump that does not follow any planned
+  /// edge (see `journey_plan::find_jump`).
+  Go { vehicle_id: u32, src_id: u32, tgt_id: u32, edge_idx: Option<u32> },
   Load { vehicle_id: u32, parcel_id: u32 },
   Unload { vehicle_id: u32, parcel_id: u32 },
 }
@@ -94,7 +98,7 @@ fn plan_leg(state: &mut State, leg: &Leg) {
   // go to the target vertex
   let src_id = state.problem.vertex_ids[leg.src as usize];
   let tgt_id = state.problem.vertex_ids[leg.tgt as usize];
-  emit_action(state, Action::Go { vehicle_id, src_id, tgt_id });
+  emit_action(state, Action::Go { vehicle_id, src_id, tgt_id, edge_idx: leg.edge_idx });
   state.vertex_vehicles[leg.src as usize].remove(&leg.vehicle);
   state.vertex_vehicles[leg.tgt as usize].insert(leg.vehicle);
 
@@ -125,6 +129,32 @@ fn plan_unloaded_parcel(state: &mut State, tgt_vehicle: u32, src: u32, tgt: u32)
   }
 }
 
+/// Builds a `Constraints` over `actions`' own indices, derived from
+/// `edge_constraints`: a Go action following edge i must precede a Go action
+/// following edge j whenever `edge_constraints` has i -> j. Actions with no
+/// corresponding edge (jumps, Load, Unload), or whose edge's constraint
+/// partner has no action in this same slice (e.g. a relation that crosses
+/// into a different journey stage), are simply left without that dependency.
+pub fn build_action_deps(edge_constraints: &Constraints, actions: &[Action]) -> Constraints {
+  let action_by_edge: FnvHashMap<u32, u32> = actions.iter().enumerate()
+    .filter_map(|(action_idx, action)| match action {
+      Action::Go { edge_idx: Some(edge_idx), .. } => Some((*edge_idx, action_idx as u32)),
+      _ => None,
+    })
+    .collect();
+
+  let mut deps = Constraints::with_capacity(actions.len());
+  for _ in 0..actions.len() { deps.push(); }
+  for (pred_edge, succ_edge) in edge_constraints.transitive_reduction() {
+    if let (Some(&pred), Some(&succ)) =
+      (action_by_edge.get(&pred_edge), action_by_edge.get(&succ_edge))
+    {
+      deps.add_before(pred, succ);
+    }
+  }
+  deps
+}
+
 fn init_state<'p>(problem: &'p ParcelProblem) -> State<'p> {
   let vertex_count = problem.vertex_ids.len();
   let vehicle_count = problem.vehicle_ids.len();
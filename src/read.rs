@@ -21,11 +21,12 @@ fn read_problem_cities_depos(input: &mut dyn io::BufRead) -> Result<Problem> {
   let city_count = read_int(input)?;
   let depo_count = read_int(input)?;
 
-  // read depos
+  // read depos, each with an optional "x y" or "x y z" coordinate suffix
   let mut city_depo_ids: Vec<Vec<u32>> = vec![Vec::new(); city_count as usize];
   let mut depos: Vec<(u32, u32)> = Vec::with_capacity(depo_count as usize);
+  let mut depo_coords: Vec<Option<(f64, f64, f64)>> = Vec::with_capacity(depo_count as usize);
   for depo_id in 0..depo_count {
-    let depo_city = read_int(input)?;
+    let (depo_city, coords) = read_depo_line(input)?;
     if depo_city >= city_count {
       return Err("Read invalid city")?;
     }
@@ -33,8 +34,20 @@ fn read_problem_cities_depos(input: &mut dyn io::BufRead) -> Result<Problem> {
     let depo_idx = city_depo_ids[depo_city as usize].len() as u32;
     city_depo_ids[depo_city as usize].push(depo_id);
     depos.push((depo_city, depo_idx));
+    depo_coords.push(coords);
   }
 
+  // coordinates are all-or-nothing: a file either has none (today's format)
+  // or has them on every depo
+  let depo_coords: Option<Vec<(f64, f64, f64)>> =
+    if depo_coords.iter().all(|c| c.is_none()) {
+      None
+    } else if depo_coords.iter().all(|c| c.is_some()) {
+      Some(depo_coords.into_iter().map(|c| c.unwrap()).collect())
+    } else {
+      return Err("Some depos have coordinates and some do not")?;
+    };
+
   // read airports
   let mut city_airport_depos: Vec<Option<u32>> = vec![None; city_count as usize];
   for _ in 0..city_count {
@@ -62,12 +75,17 @@ fn read_problem_cities_depos(input: &mut dyn io::BufRead) -> Result<Problem> {
       outbound_parcel_ids: vec![Vec::new(); depo_count],
       inbound_parcel_ids: vec![Vec::new(); depo_count],
       parcel_count: 0,
+      depo_coords: depo_coords.as_ref().map(|coords|
+        city_depo_ids[city as usize].iter().map(|&depo_id| coords[depo_id as usize]).collect()),
     }
   }).collect();
 
+  let airport_ids: Vec<u32> = city_airport_depos.iter().enumerate()
+    .map(|(city, depo_idx)| city_depo_ids[city][depo_idx.unwrap() as usize]).collect();
   let air_problem = AirProblem {
-    airport_ids: city_airport_depos.iter().enumerate()
-      .map(|(city, depo_idx)| city_depo_ids[city][depo_idx.unwrap() as usize]).collect(),
+    airport_coords: depo_coords.as_ref().map(|coords|
+      airport_ids.iter().map(|&depo_id| coords[depo_id as usize]).collect()),
+    airport_ids,
     airplane_airports: Vec::new(),
     parcel_ids: Array2D::filled_with(Vec::new(),city_count as usize,city_count as usize),
   };
@@ -170,6 +188,29 @@ fn read_int(input: &mut dyn io::BufRead) -> Result<u32> {
   Ok(read_line(input)?.trim().parse()?)
 }
 
+/// Reads a depo line: a city index, optionally followed by "x y" or "x y z"
+/// coordinates. Coordinates missing a `z` default it to 0.0.
+fn read_depo_line(input: &mut dyn io::BufRead) -> Result<(u32, Option<(f64, f64, f64)>)> {
+  let line = read_line(input)?;
+  let mut fields = line.split_whitespace();
+  let depo_city = fields.next().ok_or("Expected a city index, got none")?.parse()?;
+
+  let coords = match fields.next() {
+    None => None,
+    Some(x) => {
+      let x: f64 = x.parse()?;
+      let y: f64 = fields.next().ok_or("Expected a y coordinate after x")?.parse()?;
+      let z: f64 = fields.next().map(|z| z.parse()).transpose()?.unwrap_or(0.0);
+      if !x.is_finite() || !y.is_finite() || !z.is_finite() {
+        return Err("Depo coordinates must be finite")?;
+      }
+      Some((x, y, z))
+    }
+  };
+
+  Ok((depo_city, coords))
+}
+
 fn read_int_pair(input: &mut dyn io::BufRead) -> Result<(u32, u32)> {
   let line = read_line(input)?;
   let mut fields = line.split_whitespace();
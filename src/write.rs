@@ -1,38 +1,112 @@
 use std::io;
 use indicatif::{ProgressBar};
-use crate::{Result, Plan, parcel_plan::{Action}};
+use fnv::{FnvHashMap};
+use crate::{Result, Plan, constraints::Constraints, parcel_plan::{Action}};
 
 #[derive(Debug, Copy, Clone)]
 enum Kind { Truck, Airplane }
 
+/// Optional dependency annotation/reordering for `write_plan`. `constraints`'s
+/// node `i` must correspond to the i-th action in the concatenation of
+/// `truck_actions_1`, `airplane_actions_2`, `truck_actions_3` (in that
+/// order). When `reorder` is set, actions are written in a
+/// `Constraints::linear_extension` order of `constraints` instead of their
+/// original plan order.
+pub struct DepOptions<'c> {
+  pub constraints: &'c Constraints,
+  pub reorder: bool,
+}
+
 pub fn write_plan(output: &mut dyn io::Write,
-  plan: &Plan, bar: &ProgressBar) -> Result<()>
+  plan: &Plan, bar: &ProgressBar, deps: Option<&DepOptions>) -> Result<()>
 {
-  bar.set_message("writing truck actions (1)");
-  for action in plan.truck_actions_1.iter() {
-    write_action(output, action, Kind::Truck)?;
+  match deps {
+    None => {
+      bar.set_message("writing truck actions (1)");
+      for action in plan.truck_actions_1.iter() {
+        write_action(output, action, Kind::Truck, &[])?;
+      }
+
+      bar.set_message("writing air actions (2)");
+      for action in plan.airplane_actions_2.iter() {
+        write_action(output, action, Kind::Airplane, &[])?;
+      }
+
+      bar.set_message("writing truck actions (3)");
+      for action in plan.truck_actions_3.iter() {
+        write_action(output, action, Kind::Truck, &[])?;
+      }
+
+      Ok(())
+    }
+    Some(deps) => write_plan_with_deps(output, plan, bar, deps),
   }
+}
+
+/// Writes the plan's actions annotated with (and, if `deps.reorder` is set,
+/// ordered by) the transitive reduction of `deps.constraints`: a minimal
+/// dependency graph showing which earlier action each action directly
+/// depends on, instead of the quadratic noise of the full transitive
+/// closure.
+fn write_plan_with_deps(output: &mut dyn io::Write,
+  plan: &Plan, bar: &ProgressBar, deps: &DepOptions) -> Result<()>
+{
+  let actions: Vec<(Kind, &Action)> =
+    plan.truck_actions_1.iter().map(|a| (Kind::Truck, a))
+      .chain(plan.airplane_actions_2.iter().map(|a| (Kind::Airplane, a)))
+      .chain(plan.truck_actions_3.iter().map(|a| (Kind::Truck, a)))
+      .collect();
 
-  bar.set_message("writing air actions (2)");
-  for action in plan.airplane_actions_2.iter() {
-    write_action(output, action, Kind::Airplane)?;
+  if deps.constraints.count() as usize != actions.len() {
+    return Err(format!("DepOptions.constraints has {} nodes, but the plan has {} actions",
+      deps.constraints.count(), actions.len()))?;
   }
 
-  bar.set_message("writing truck actions (3)");
-  for action in plan.truck_actions_3.iter() {
-    write_action(output, action, Kind::Truck)?;
+  // group the reduced covering edges by their target action, so each
+  // action's direct dependencies can be looked up without re-scanning the
+  // whole reduction for every action
+  let mut preds_by_action: FnvHashMap<u32, Vec<u32>> = FnvHashMap::default();
+  for (pred, succ) in deps.constraints.transitive_reduction() {
+    preds_by_action.entry(succ).or_insert_with(Vec::new).push(pred);
+  }
+
+  let order: Vec<u32> = if deps.reorder {
+    // a partial result here would mean deps.constraints has a cycle, which
+    // should never happen; surface it rather than silently truncating the
+    // output to only the actions Kahn's algorithm got through
+    deps.constraints.linear_extension()
+      .map_err(|_| "Cannot reorder actions: dependency graph has a cycle")?
+  } else {
+    (0..actions.len() as u32).collect()
+  };
+
+  bar.set_message("writing actions (with dependency annotations)");
+  bar.set_length(order.len() as u64);
+  for action_idx in order {
+    let (kind, action) = actions[action_idx as usize];
+    let empty = Vec::new();
+    let preds = preds_by_action.get(&action_idx).unwrap_or(&empty);
+    write_action(output, action, kind, preds)?;
+    bar.inc(1);
   }
 
   Ok(())
 }
 
-fn write_action(output: &mut dyn io::Write, action: &Action, kind: Kind) -> Result<()> {
+fn write_action(output: &mut dyn io::Write, action: &Action, kind: Kind,
+  depends_on: &[u32]) -> Result<()>
+{
   let (go, load, unload) = match kind {
     Kind::Truck => ("drive", "load", "unload"),
     Kind::Airplane => ("fly", "pickup", "dropoff"),
   };
+
+  for &pred in depends_on {
+    write!(output, "% depends on {}\n", pred)?;
+  }
+
   match action {
-    Action::Go { vehicle_id, src_id: _, tgt_id } =>
+    Action::Go { vehicle_id, src_id: _, tgt_id, edge_idx: _ } =>
       write!(output, "{} {} {}\n", go, vehicle_id, tgt_id)?,
     Action::Load { vehicle_id, parcel_id } =>
       write!(output, "{} {} {}\n", load, vehicle_id, parcel_id)?,
@@ -41,3 +115,89 @@ fn write_action(output: &mut dyn io::Write, action: &Action, kind: Kind) -> Resu
   };
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample_plan() -> Plan {
+    Plan {
+      truck_actions_1: vec![
+        Action::Load { vehicle_id: 0, parcel_id: 1 },
+        Action::Go { vehicle_id: 0, src_id: 0, tgt_id: 2, edge_idx: None },
+      ],
+      airplane_actions_2: vec![],
+      truck_actions_3: vec![],
+      cost: 0,
+      min_cost: 0,
+      jump_distance: 0.0,
+      action_deps: Constraints::new(),
+    }
+  }
+
+  fn write_to_string(plan: &Plan, deps: Option<&DepOptions>) -> String {
+    let mut output = Vec::new();
+    write_plan(&mut output, plan, &ProgressBar::hidden(), deps).unwrap();
+    String::from_utf8(output).unwrap()
+  }
+
+  #[test]
+  fn test_write_plan_with_deps_annotates_dependencies() {
+    let plan = sample_plan();
+    let mut constraints = Constraints::new();
+    constraints.push();
+    constraints.push();
+    constraints.add_before(0, 1);
+
+    let deps = DepOptions { constraints: &constraints, reorder: false };
+    let text = write_to_string(&plan, Some(&deps));
+    assert_eq!(text, "load 0 1\n% depends on 0\ndrive 0 2\n");
+  }
+
+  #[test]
+  fn test_write_plan_with_deps_reorders() {
+    let plan = sample_plan();
+    let mut constraints = Constraints::new();
+    constraints.push();
+    constraints.push();
+    constraints.add_before(1, 0);
+
+    let deps = DepOptions { constraints: &constraints, reorder: true };
+    let text = write_to_string(&plan, Some(&deps));
+    assert_eq!(text, "drive 0 2\n% depends on 1\nload 0 1\n");
+  }
+
+  #[test]
+  fn test_write_plan_with_deps_indexes_across_all_three_action_groups() {
+    // node i of constraints must correspond to the i-th action of
+    // truck_actions_1 ++ airplane_actions_2 ++ truck_actions_3, not just
+    // within a single group
+    let plan = Plan {
+      truck_actions_1: vec![Action::Load { vehicle_id: 0, parcel_id: 1 }],
+      airplane_actions_2: vec![
+        Action::Go { vehicle_id: 1, src_id: 5, tgt_id: 6, edge_idx: None },
+      ],
+      truck_actions_3: vec![Action::Unload { vehicle_id: 0, parcel_id: 1 }],
+      cost: 0,
+      min_cost: 0,
+      jump_distance: 0.0,
+      action_deps: Constraints::new(),
+    };
+    let mut constraints = Constraints::new();
+    for _ in 0..3 { constraints.push(); }
+    constraints.add_before(1, 2); // the airplane leg must precede the unload
+
+    let deps = DepOptions { constraints: &constraints, reorder: false };
+    let text = write_to_string(&plan, Some(&deps));
+    assert_eq!(text, "load 0 1\nfly 1 6\n% depends on 1\nunload 0 1\n");
+  }
+
+  #[test]
+  fn test_write_plan_with_deps_rejects_mismatched_action_count() {
+    let plan = sample_plan();
+    let constraints = Constraints::new();
+    let deps = DepOptions { constraints: &constraints, reorder: false };
+    let mut output = Vec::new();
+    assert!(write_plan(&mut output, &plan, &ProgressBar::hidden(), Some(&deps)).is_err());
+  }
+}